@@ -1,8 +1,24 @@
-use clap::Parser;
+use crate::util::zone::{check_ipv4_reverse_prefix, check_ipv6_reverse_prefix};
+use cidr::{IpCidr, Ipv4Cidr, Ipv6Cidr};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Parser, Clone, Debug)]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Config {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// Registry data source: `git` clones and fetches a git repository, `directory` reads a local directory
+    #[clap(long, value_enum, default_value = "git", env = "SOURCE")]
+    pub source: SourceKind,
+
+    /// Registry directory path, read when the data source is `directory`
+    #[clap(long, env = "REGISTRY_DIRECTORY", required_if_eq("source", "directory"))]
+    pub directory: Option<PathBuf>,
+
     /// Git repository store path
     #[clap(long, short = 'p', default_value = "registry", env = "GIT_PATH")]
     pub git_path: String,
@@ -13,16 +29,165 @@ pub struct Config {
 
     /// Git repository URL
     #[clap(long, short = 'u', env = "GIT_REPO")]
-    pub git_repo: String,
+    pub git_repo: Option<String>,
+
+    /// Handling of invalid registry objects: `warn` logs and leaves them out, `strict` rejects the whole registry
+    #[clap(long, value_enum, default_value = "warn", env = "VALIDATION")]
+    pub validation: ValidationMode,
+
+    /// GPG keyring of the keys trusted to sign registry commits, commits not being checked without trusted keys
+    #[clap(long, env = "TRUSTED_GPG_KEYRING")]
+    pub trusted_gpg_keyring: Option<PathBuf>,
+
+    /// SSH allowed signers file (as in `gpg.ssh.allowedSignersFile`) of the keys trusted to sign registry commits
+    #[clap(long, env = "TRUSTED_SSH_SIGNERS")]
+    pub trusted_ssh_signers: Option<PathBuf>,
+
+    /// Commits that must be signed by a trusted key: `all` the fetched commits, or only the `head` of the branch
+    #[clap(long, value_enum, default_value = "all", env = "SIGNATURE_POLICY")]
+    pub signature_policy: SignaturePolicy,
 
     /// DNS listen addresses
     #[clap(long, short = 'd', env = "DNS_ADDR")]
     pub dns: Vec<SocketAddr>,
 
+    /// DNS TCP connection idle timeout (in seconds)
+    #[clap(long, default_value = "10", env = "DNS_TCP_TIMEOUT")]
+    pub dns_tcp_timeout: u64,
+
+    /// Largest UDP payload of responses, advertised over EDNS
+    #[clap(long, default_value = "1232", env = "DNS_UDP_PAYLOAD")]
+    pub dns_udp_payload: u16,
+
+    /// DNS cookie secret (32 hex digits), shared by all nodes so that they accept the cookies of each other
+    #[clap(long, env = "DNS_COOKIE_SECRET")]
+    pub dns_cookie_secret: Option<String>,
+
+    /// Responses per second allowed to a client prefix over UDP for each kind of response, 0 disables rate limiting
+    #[clap(long, default_value = "0", env = "RRL_RESPONSES_PER_SECOND")]
+    pub rrl_responses_per_second: u32,
+
+    /// Rate limiting window (in seconds), the longest a limited client stays limited after it stopped querying
+    #[clap(long, default_value = "15", env = "RRL_WINDOW")]
+    pub rrl_window: u64,
+
+    /// Every n-th limited response is sent truncated instead of dropped, 0 drops them all
+    #[clap(long, default_value = "2", env = "RRL_SLIP")]
+    pub rrl_slip: u32,
+
+    /// Prefix length of the IPv4 clients accounted together
+    #[clap(long, default_value = "24", env = "RRL_IPV4_PREFIX", value_parser = clap::value_parser!(u8).range(0..=32))]
+    pub rrl_ipv4_prefix: u8,
+
+    /// Prefix length of the IPv6 clients accounted together
+    #[clap(long, default_value = "56", env = "RRL_IPV6_PREFIX", value_parser = clap::value_parser!(u8).range(0..=128))]
+    pub rrl_ipv6_prefix: u8,
+
+    /// Clients exempt from rate limiting, e.g. the resolvers forwarding to us
+    #[clap(long, env = "RRL_EXEMPT", value_delimiter = ',')]
+    pub rrl_exempt: Vec<IpCidr>,
+
+    /// Forward zones (TLDs) served
+    #[clap(long = "zone", default_value = "catmunch", env = "ZONES", value_delimiter = ',')]
+    pub zones: Vec<String>,
+
+    /// IPv4 prefixes whose reverse zones are served (octet-aligned)
+    #[clap(long, default_value = "10.0.0.0/8", env = "RDNS_IPV4", value_delimiter = ',', value_parser = octet_aligned)]
+    pub rdns_ipv4: Vec<Ipv4Cidr>,
+
+    /// IPv6 prefixes whose reverse zones are served (nibble-aligned)
+    #[clap(long, default_value = "fc75::/16", env = "RDNS_IPV6", value_delimiter = ',', value_parser = nibble_aligned)]
+    pub rdns_ipv6: Vec<Ipv6Cidr>,
+
+    /// Primary nameserver (MNAME) in the SOA of served zones
+    #[clap(long, default_value = "ns.catmunch.", env = "SOA_MNAME")]
+    pub soa_mname: String,
+
+    /// Responsible mailbox (RNAME) in the SOA of served zones
+    #[clap(long, default_value = "hostmaster.catmunch.", env = "SOA_RNAME")]
+    pub soa_rname: String,
+
+    /// SOA refresh timer (in seconds)
+    #[clap(long, default_value = "3600", env = "SOA_REFRESH")]
+    pub soa_refresh: i32,
+
+    /// SOA retry timer (in seconds)
+    #[clap(long, default_value = "600", env = "SOA_RETRY")]
+    pub soa_retry: i32,
+
+    /// SOA expire timer (in seconds)
+    #[clap(long, default_value = "604800", env = "SOA_EXPIRE")]
+    pub soa_expire: i32,
+
+    /// SOA minimum / negative caching TTL (in seconds)
+    #[clap(long, default_value = "300", env = "SOA_MINIMUM")]
+    pub soa_minimum: u32,
+
+    /// Apex NS names of served zones, defaults to the SOA MNAME
+    #[clap(long, env = "APEX_NS", value_delimiter = ',')]
+    pub apex_ns: Vec<String>,
+
+    /// DNSSEC signing key (PKCS#8, PEM or DER), responses are signed when set and zone transfers are refused, as
+    /// the signatures and denials are made per response and cannot be transferred
+    #[clap(long, env = "DNSSEC_KEY")]
+    pub dnssec_key: Option<String>,
+
+    /// DNSSEC algorithm number of the signing key (13 = ECDSAP256SHA256, 14 = ECDSAP384SHA384, 15 = ED25519)
+    #[clap(long, default_value = "13", env = "DNSSEC_ALGORITHM")]
+    pub dnssec_algorithm: u8,
+
+    /// DNSSEC signature validity (in seconds)
+    #[clap(long, default_value = "604800", env = "DNSSEC_SIGNATURE_VALIDITY")]
+    pub dnssec_signature_validity: u32,
+
+    /// Client prefixes allowed to transfer the zones (AXFR/IXFR)
+    #[clap(long, env = "TRANSFER_ACL", value_delimiter = ',')]
+    pub transfer_acl: Vec<IpCidr>,
+
+    /// Secondaries notified when the zones are updated (NOTIFY)
+    #[clap(long, env = "NOTIFY_TARGETS", value_delimiter = ',')]
+    pub notify: Vec<SocketAddr>,
+
+    /// NOTIFY attempts per secondary before giving up
+    #[clap(long, default_value = "5", env = "NOTIFY_RETRIES")]
+    pub notify_retries: u32,
+
+    /// Delay before the first NOTIFY retry, doubled after each attempt (in seconds)
+    #[clap(long, default_value = "15", env = "NOTIFY_RETRY_INTERVAL")]
+    pub notify_retry_interval: u64,
+
     /// WHOIS listen addresses
     #[clap(long, short = 'w', env = "WHOIS_ADDR")]
     pub whois: Vec<SocketAddr>,
 
+    /// WHOIS request read and response write timeout (in seconds)
+    #[clap(long, default_value = "10", env = "WHOIS_TIMEOUT")]
+    pub whois_timeout: u64,
+
+    /// Maximum number of concurrent WHOIS connections, others are turned away
+    #[clap(long, default_value = "256", env = "WHOIS_MAX_CONNECTIONS")]
+    pub whois_max_connections: usize,
+
+    /// WHOIS queries per minute allowed to a source IP, 0 disables rate limiting
+    #[clap(long, default_value = "60", env = "WHOIS_QUERIES_PER_MINUTE")]
+    pub whois_queries_per_minute: u32,
+
+    /// Default WHOIS output format, queries may pick another with a leading `--rpsl`, `--yaml` or `--json`
+    #[clap(long, value_enum, default_value = "rpsl", env = "WHOIS_FORMAT")]
+    pub whois_format: WhoisFormat,
+
+    /// Registry name given as the `source:` of RPSL objects
+    #[clap(long, default_value = "CATMUNCH", env = "WHOIS_SOURCE")]
+    pub whois_source: String,
+
+    /// Include the persons, roles and mntners referenced by the objects in WHOIS responses
+    #[clap(long, env = "WHOIS_CONTACTS")]
+    pub whois_contacts: bool,
+
+    /// RDAP listen addresses
+    #[clap(long, env = "RDAP_ADDR")]
+    pub rdap: Vec<SocketAddr>,
+
     /// Update interval (in seconds)
     #[clap(long, short = 'i', default_value = "300", env = "INTERVAL")]
     pub interval: u64,
@@ -35,3 +200,68 @@ pub struct Config {
     #[clap(long, default_value = "8080", env = "HEALTH_CHECK_PORT")]
     pub health_check_port: u16
 }
+
+impl Config {
+    /// Parses the command line, the git repository URL being only required by the git data source, which
+    /// clap cannot tell from the default value of `--source`
+    pub fn parse_args() -> Self {
+        let config = Self::parse();
+        if config.command.is_none() && config.source == SourceKind::Git && config.git_repo.is_none() {
+            Self::command()
+                .error(ErrorKind::MissingRequiredArgument, "--git-repo is required by the git data source")
+                .exit();
+        }
+        config
+    }
+}
+
+/// Parses an IPv4 prefix whose reverse zone can be served
+fn octet_aligned(value: &str) -> Result<Ipv4Cidr, String> {
+    let cidr: Ipv4Cidr = value.parse().map_err(|e| format!("{}", e))?;
+    check_ipv4_reverse_prefix(&cidr).map_err(|e| e.to_string())?;
+    Ok(cidr)
+}
+
+/// Parses an IPv6 prefix whose reverse zone can be served
+fn nibble_aligned(value: &str) -> Result<Ipv6Cidr, String> {
+    let cidr: Ipv6Cidr = value.parse().map_err(|e| format!("{}", e))?;
+    check_ipv6_reverse_prefix(&cidr).map_err(|e| e.to_string())?;
+    Ok(cidr)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhoisFormat {
+    Rpsl,
+    Yaml,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    Git,
+    Directory,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationMode {
+    Warn,
+    Strict,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    Head,
+    All,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Check every object of a registry checkout and report all the problems found, without serving it
+    Lint {
+        /// Registry checkout path
+        path: PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests;
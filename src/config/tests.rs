@@ -0,0 +1,18 @@
+use super::*;
+
+#[test]
+fn test_config_reverse_prefixes() {
+    let parse = |option: &str, prefixes: &str| Config::try_parse_from(["dns-whois-server", option, prefixes]);
+    assert!(parse("--rdns-ipv4", "10.0.0.0/8,172.20.0.0/16").is_ok());
+    assert!(parse("--rdns-ipv4", "10.0.0.0/8,172.20.0.0/14").is_err());
+    assert!(parse("--rdns-ipv6", "fc75::/16,fd00::/8").is_ok());
+    assert!(parse("--rdns-ipv6", "fc75::/15").is_err());
+}
+#[test]
+fn test_config_rrl_prefixes() {
+    let parse = |option: &str, length: &str| Config::try_parse_from(["dns-whois-server", option, length]);
+    assert!(parse("--rrl-ipv4-prefix", "32").is_ok());
+    assert!(parse("--rrl-ipv4-prefix", "33").is_err());
+    assert!(parse("--rrl-ipv6-prefix", "128").is_ok());
+    assert!(parse("--rrl-ipv6-prefix", "129").is_err());
+}
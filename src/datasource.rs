@@ -1,7 +1,104 @@
 use crate::resource::Resource;
+use crate::store::Change;
+use crate::validation::ValidationError;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
+pub mod directory;
 pub mod git;
+pub mod registry;
+pub mod signature;
+#[cfg(test)]
+mod tests;
 pub trait DataSource: Send + Sync {
-    fn update(&mut self) -> bool;
-    fn get_resources(&self) -> Vec<Resource>;
+    /// Brings the registry to its latest revision
+    fn update(&mut self) -> Result<UpdateOutcome, DataSourceError>;
+    /// Resources of the current revision, or every problem preventing it from being served
+    fn get_resources(&mut self) -> Result<Vec<Resource>, Vec<DataSourceError>>;
+    /// Changes of the served resources since the previous `get_resources` or `get_changes`, only the files
+    /// changed by the updates since then being read again
+    fn get_changes(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>>;
+    fn get_serial(&self) -> Result<u32, DataSourceError>;
+    /// Identifier of the current revision, such as a commit id
+    fn get_revision(&self) -> Result<String, DataSourceError>;
 }
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpdateOutcome {
+    Unchanged,
+    /// The registry moved to the given revision
+    Updated(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataSourceError {
+    /// The remote registry cannot be cloned or fetched
+    Fetch(String),
+    /// The local copy of the registry cannot be read or checked out
+    Repository(String),
+    /// A fetched commit is not signed by a trusted key
+    Signature(String),
+    /// A directory of objects required in every registry is missing or unreadable
+    MissingDirectory(PathBuf),
+    /// An object file cannot be read
+    Read { file: PathBuf, message: String },
+    /// An object file is not valid YAML for its class, `line` being where the parser stopped
+    Parse {
+        file: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    /// An object is invalid, alone or together with the rest of the registry
+    Validation(ValidationError),
+}
+
+impl DataSourceError {
+    /// File or directory of the registry the error is about
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            DataSourceError::Fetch(_) | DataSourceError::Repository(_) | DataSourceError::Signature(_) => None,
+            DataSourceError::MissingDirectory(directory) => Some(directory),
+            DataSourceError::Read { file, .. } | DataSourceError::Parse { file, .. } => Some(file),
+            DataSourceError::Validation(error) => Some(&error.file),
+        }
+    }
+    /// Whether the object of the file is still served despite the error, as it is only about a missing object
+    /// it references
+    pub fn is_reference(&self) -> bool {
+        matches!(self, DataSourceError::Validation(error) if error.reference)
+    }
+}
+
+impl Display for DataSourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSourceError::Fetch(message) => write!(f, "unable to fetch the registry: {}", message),
+            DataSourceError::Repository(message) => write!(f, "unable to read the registry: {}", message),
+            DataSourceError::Signature(message) => write!(f, "untrusted signature, {}", message),
+            DataSourceError::MissingDirectory(directory) => write!(f, "{}: missing directory", directory.display()),
+            DataSourceError::Read { file, message } => write!(f, "{}: unable to read: {}", file.display(), message),
+            DataSourceError::Parse { file, message, .. } => write!(f, "{}: unable to parse: {}", file.display(), message),
+            DataSourceError::Validation(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for DataSourceError {}
+
+impl From<git2::Error> for DataSourceError {
+    fn from(e: git2::Error) -> Self {
+        DataSourceError::Repository(e.message().to_string())
+    }
+}
+
+/// Revision that could not be loaded, the previous resources being served instead
+#[derive(Clone, Debug, Serialize)]
+pub struct RejectedUpdate {
+    pub revision: String,
+    pub errors: Vec<String>,
+}
+
+/// Latest rejected update, cleared once a revision is loaded, shared with the health check
+pub type UpdateStatus = Arc<RwLock<Option<RejectedUpdate>>>;
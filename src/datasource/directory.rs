@@ -0,0 +1,140 @@
+use crate::config::Config;
+use crate::datasource::registry::{Registry, DIRECTORIES};
+use crate::datasource::{DataSource, DataSourceError, UpdateOutcome};
+use crate::resource::Resource;
+use crate::store::Change;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Contents hash of a file, with the modification time and size it was computed at
+#[derive(Clone, Copy, PartialEq)]
+struct FileHash {
+    modified: SystemTime,
+    size: u64,
+    hash: u64,
+}
+
+/// Hashes of the files of the object directories, by path relative to the registry
+type Hashes = BTreeMap<PathBuf, FileHash>;
+
+/// Registry read from a plain directory, changes being detected by hashing its files at every update
+pub struct DirectoryDataSource {
+    path: PathBuf,
+    registry: Registry,
+    /// Hashes of the files of the latest revision, identifying it
+    hashes: Option<Hashes>,
+    /// Paths changed by the updates since the registry was last read
+    changed: Vec<PathBuf>,
+    serial: u32,
+}
+
+/// Hashes the files of the object directories of `root`, skipping dotfiles, the files whose modification time
+/// and size are those of `previous` keeping their hash without being read
+fn hash_directories(root: &Path, previous: &Hashes) -> Result<Hashes, DataSourceError> {
+    let mut hashes = Hashes::new();
+    for (directory, _) in DIRECTORIES {
+        // the registry reports the missing directories
+        let Ok(entries) = fs::read_dir(root.join(directory)) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let read_error = |e: std::io::Error| DataSourceError::Read {
+                file: path.clone(),
+                message: e.to_string(),
+            };
+            let metadata = fs::metadata(&path).map_err(read_error)?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let (modified, size) = (metadata.modified().map_err(read_error)?, metadata.len());
+            let relative = Path::new(directory).join(entry.file_name());
+            let hash = match previous.get(&relative) {
+                Some(file) if file.modified == modified && file.size == size => file.hash,
+                _ => {
+                    let mut hasher = DefaultHasher::new();
+                    fs::read(&path).map_err(read_error)?.hash(&mut hasher);
+                    hasher.finish()
+                }
+            };
+            hashes.insert(relative, FileHash { modified, size, hash });
+        }
+    }
+    Ok(hashes)
+}
+
+/// Identifier of a revision, the hash of the contents hashes of its files
+fn revision(hashes: &Hashes) -> String {
+    let mut hasher = DefaultHasher::new();
+    for (path, file) in hashes {
+        (path, file.hash).hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+impl DataSource for DirectoryDataSource {
+    fn update(&mut self) -> Result<UpdateOutcome, DataSourceError> {
+        if !self.path.is_dir() {
+            return Err(DataSourceError::Repository(format!("{} is not a directory", self.path.display())));
+        }
+        let previous = self.hashes.take().unwrap_or_default();
+        let hashes = hash_directories(&self.path, &previous)?;
+        let hash = |hashes: &Hashes, path: &PathBuf| hashes.get(path).map(|file| file.hash);
+        let changed: Vec<PathBuf> = previous
+            .keys()
+            .filter(|path| hash(&previous, path) != hash(&hashes, path))
+            .chain(hashes.keys().filter(|path| !previous.contains_key(*path)))
+            .cloned()
+            .collect();
+        if changed.is_empty() {
+            self.hashes = Some(hashes);
+            return Ok(UpdateOutcome::Unchanged);
+        }
+        self.changed.extend(changed);
+        // the serial must keep increasing for the secondaries, even when files are deleted or restored
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        self.serial = now.max(self.serial + 1);
+        let outcome = UpdateOutcome::Updated(revision(&hashes));
+        self.hashes = Some(hashes);
+        Ok(outcome)
+    }
+    fn get_resources(&mut self) -> Result<Vec<Resource>, Vec<DataSourceError>> {
+        self.changed.clear();
+        self.registry.load()
+    }
+    fn get_changes(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>> {
+        let changed = std::mem::take(&mut self.changed);
+        self.registry.reload(&changed)
+    }
+    fn get_serial(&self) -> Result<u32, DataSourceError> {
+        match self.hashes {
+            Some(_) => Ok(self.serial),
+            None => Err(DataSourceError::Repository("the directory has not been read yet".to_string())),
+        }
+    }
+    fn get_revision(&self) -> Result<String, DataSourceError> {
+        self.hashes
+            .as_ref()
+            .map(revision)
+            .ok_or_else(|| DataSourceError::Repository("the directory has not been read yet".to_string()))
+    }
+}
+impl DirectoryDataSource {
+    pub fn new(config: Config) -> Self {
+        let path = config.directory.expect("--directory is required");
+        Self {
+            registry: Registry::new(&path, config.validation),
+            path,
+            hashes: None,
+            changed: vec![],
+            serial: 0,
+        }
+    }
+}
@@ -1,149 +1,145 @@
 use crate::config::Config;
-use crate::datasource::DataSource;
-use crate::resource::autnum::Autnum;
-use crate::resource::domain::Domain;
-use crate::resource::inet6num::Inet6num;
-use crate::resource::inetnum::Inetnum;
-use crate::resource::route::Route;
-use crate::resource::route6::Route6;
+use crate::datasource::{DataSource, DataSourceError, UpdateOutcome};
+use crate::datasource::registry::Registry;
+use crate::datasource::signature::SignatureVerifier;
 use crate::resource::Resource;
-use git2::Repository;
-use log::{info, warn};
+use crate::store::Change;
+use git2::{Commit, Repository};
+use log::info;
 use std::fs;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct GitDataSource {
     git_path: String,
     git_branch: String,
     git_repo: String,
+    verifier: Option<SignatureVerifier>,
+    /// Whether the head of the checkout was verified, a checkout found at startup not being served before
+    verified: bool,
+    registry: Registry,
+    /// Paths changed by the updates since the registry was last read, relative to the checkout, or `None` when
+    /// the whole checkout was replaced
+    changed: Option<Vec<PathBuf>>,
+    /// Serial of the checkout, 0 before it was first read
+    serial: u32,
 }
+
+fn head_commit(repo: &Repository) -> Result<Commit<'_>, DataSourceError> {
+    Ok(repo.head()?.peel_to_commit()?)
+}
+
 impl DataSource for GitDataSource {
-    fn update(&mut self) -> bool {
+    fn update(&mut self) -> Result<UpdateOutcome, DataSourceError> {
         if !Path::new(&self.git_path).exists() {
             info!("Cannot find registry, cloning from git");
-            Repository::clone(&self.git_repo, &self.git_path).expect("Failed to clone git repo!");
-            true
-        } else {
-            let repo =
-                Repository::open(&self.git_path).expect("git_path exists but is not a git repo");
-            let mut remote = repo
-                .remote_anonymous(&self.git_repo)
-                .expect("Invalid git repo url!");
-            if remote.fetch(&[&self.git_branch], None, None).is_err() {
-                warn!("Unable to fetch from remote!");
-                false
-            } else {
-                let fetch_head = repo
-                    .find_reference("FETCH_HEAD")
-                    .expect("Cannot get FETCH_HEAD");
-                let ref_name = format!("refs/heads/{}", &self.git_branch);
-                let mut reference = repo
-                    .find_reference(&ref_name)
-                    .expect("Cannot find git branch!");
-                if reference.target() == fetch_head.target() {
-                    return false;
-                }
-                reference
-                    .set_target(
-                        fetch_head
-                            .target()
-                            .expect("Cannot get the Oid of FETCH_HEAD"),
-                        "",
-                    )
-                    .expect("Unable to set target");
-                repo.set_head(&ref_name).unwrap();
-                repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
-                    .unwrap();
-                true
+            let repo = Repository::clone(&self.git_repo, &self.git_path)
+                .map_err(|e| DataSourceError::Fetch(e.message().to_string()))?;
+            self.changed = None;
+            let head = head_commit(&repo)?.id();
+            if let Some(Err(e)) = self.verifier.as_ref().map(|verifier| verifier.verify_update(&repo, None, head)) {
+                // the untrusted checkout must not be served, it is cloned again at the next update
+                let _ = fs::remove_dir_all(&self.git_path);
+                return Err(e);
             }
+            self.verified = true;
+            self.advance_serial(&repo)?;
+            return Ok(UpdateOutcome::Updated(head.to_string()));
         }
-    }
-    fn get_resources(&self) -> Vec<Resource> {
-        let mut resources: Vec<Resource> = Vec::new();
-        let git_path = Path::new(&self.git_path);
-        let autnum_path = git_path.join("autnum");
-        for entry in fs::read_dir(autnum_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
-            }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", &path.to_str().unwrap()));
-            let autnum: Autnum = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Autnum(autnum));
+        let repo = Repository::open(&self.git_path)?;
+        if self.serial == 0 {
+            self.advance_serial(&repo)?;
         }
-        let domain_path = git_path.join("domain");
-        for entry in fs::read_dir(domain_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
-            }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", path.to_str().unwrap()));
-            let domain: Domain = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Domain(domain));
+        if let (false, Some(verifier)) = (self.verified, &self.verifier) {
+            // served even when the fetch fails, the error being reported below when the head stays the same
+            self.verified = verifier.verify(&repo, head_commit(&repo)?.id()).is_ok();
         }
-        let inetnum_path = git_path.join("inetnum");
-        for entry in fs::read_dir(inetnum_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
-            }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", path.to_str().unwrap()));
-            let inetnum: Inetnum = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Inetnum(inetnum));
+        repo.remote_anonymous(&self.git_repo)
+            .and_then(|mut remote| remote.fetch(&[&self.git_branch], None, None))
+            .map_err(|e| DataSourceError::Fetch(e.message().to_string()))?;
+        let fetch_head = repo
+            .find_reference("FETCH_HEAD")?
+            .target()
+            .ok_or_else(|| DataSourceError::Repository("FETCH_HEAD is not a commit".to_string()))?;
+        let ref_name = format!("refs/heads/{}", &self.git_branch);
+        let mut reference = repo.find_reference(&ref_name)?;
+        if reference.target() == Some(fetch_head) && self.verified {
+            return Ok(UpdateOutcome::Unchanged);
         }
-        let inet6num_path = git_path.join("inet6num");
-        for entry in fs::read_dir(inet6num_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
-            }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", path.to_str().unwrap()));
-            let inet6num: Inet6num = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Inet6num(inet6num));
+        if let Some(verifier) = &self.verifier {
+            // the commits of an unverified checkout are not a trusted base, only the new head is checked then
+            verifier.verify_update(&repo, reference.target().filter(|_| self.verified), fetch_head)?;
         }
-        let route_path = git_path.join("route");
-        for entry in fs::read_dir(route_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
-            }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", path.to_str().unwrap()));
-            let route: Route = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Route(route));
+        self.verified = true;
+        if reference.target() == Some(fetch_head) {
+            return Ok(UpdateOutcome::Unchanged);
         }
-        let route6_path = git_path.join("route6");
-        for entry in fs::read_dir(route6_path).unwrap() {
-            let path = entry.unwrap().path();
-            if path.file_name().unwrap().to_str().unwrap().starts_with(".") {
-                continue;
+        if let (Some(previous), Some(changed)) = (reference.target(), &mut self.changed) {
+            let previous = repo.find_commit(previous)?.tree()?;
+            let next = repo.find_commit(fetch_head)?.tree()?;
+            for delta in repo.diff_tree_to_tree(Some(&previous), Some(&next), None)?.deltas() {
+                changed.extend([delta.old_file().path(), delta.new_file().path()].into_iter().flatten().map(Path::to_path_buf));
             }
-            let file = File::open(&path)
-                .expect(&format!("Unable to open file {}", path.to_str().unwrap()));
-            let route6: Route6 = serde_yaml::from_reader(BufReader::new(file))
-                .expect(&format!("Unable to parse {}", path.to_str().unwrap()));
-            resources.push(Resource::Route6(route6));
+        } else {
+            self.changed = None;
         }
-        resources
+        reference.set_target(fetch_head, "")?;
+        repo.set_head(&ref_name)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+        self.advance_serial(&repo)?;
+        Ok(UpdateOutcome::Updated(fetch_head.to_string()))
+    }
+    fn get_resources(&mut self) -> Result<Vec<Resource>, Vec<DataSourceError>> {
+        self.check_verified()?;
+        self.changed = Some(vec![]);
+        self.registry.load()
+    }
+    fn get_changes(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>> {
+        self.check_verified()?;
+        match self.changed.replace(vec![]) {
+            Some(changed) => self.registry.reload(&changed),
+            None => self.registry.reload_all(),
+        }
+    }
+    fn get_serial(&self) -> Result<u32, DataSourceError> {
+        match self.serial {
+            0 => Err(DataSourceError::Repository("the registry has not been read yet".to_string())),
+            serial => Ok(serial),
+        }
+    }
+    fn get_revision(&self) -> Result<String, DataSourceError> {
+        let repo = Repository::open(&self.git_path)?;
+        let revision = head_commit(&repo)?.id().to_string();
+        Ok(revision)
     }
 }
 impl GitDataSource {
     pub fn new(config: Config) -> Self {
+        let verifier = SignatureVerifier::new(&config);
+        if let Some(Err(e)) = verifier.as_ref().map(SignatureVerifier::check_environment) {
+            panic!("Unable to verify the registry commits: {}", e);
+        }
         Self {
+            registry: Registry::new(Path::new(&config.git_path), config.validation),
             git_path: config.git_path,
             git_branch: config.git_branch,
-            git_repo: config.git_repo,
+            git_repo: config.git_repo.expect("--git-repo is required"),
+            verified: verifier.is_none(),
+            verifier,
+            changed: Some(vec![]),
+            serial: 0,
+        }
+    }
+    /// Takes the time of the head commit as the serial, which must keep increasing for the secondaries even when
+    /// the branch goes back to an older commit or a commit is back-dated
+    fn advance_serial(&mut self, repo: &Repository) -> Result<(), DataSourceError> {
+        let time = head_commit(repo)?.time().seconds() as u32;
+        self.serial = time.max(self.serial + 1);
+        Ok(())
+    }
+        fn check_verified(&self) -> Result<(), Vec<DataSourceError>> {
+        if self.verified {
+            return Ok(());
         }
+        Err(vec![DataSourceError::Signature("the head of the checkout has not been verified".to_string())])
     }
 }
@@ -0,0 +1,210 @@
+use crate::config::ValidationMode;
+use crate::datasource::DataSourceError;
+use crate::resource::Resource;
+use crate::store::Change;
+use crate::validation::validate;
+use log::warn;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Resources of a registry checkout by the file they were read from
+pub type Files = BTreeMap<PathBuf, Resource>;
+
+/// Directories of objects, and whether they are required, as registries predating sets and contacts do not
+/// have their directories
+pub(crate) const DIRECTORIES: [(&str, bool); 11] = [
+    ("autnum", true),
+    ("domain", true),
+    ("inetnum", true),
+    ("inet6num", true),
+    ("route", true),
+    ("route6", true),
+    ("as-set", false),
+    ("route-set", false),
+    ("person", false),
+    ("role", false),
+    ("mntner", false),
+];
+
+fn parse<T: DeserializeOwned>(path: &Path, resource: fn(T) -> Resource) -> Result<Resource, DataSourceError> {
+    let file = File::open(path).map_err(|e| DataSourceError::Read {
+        file: path.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    serde_yaml::from_reader(BufReader::new(file))
+        .map(resource)
+        .map_err(|e| DataSourceError::Parse {
+            file: path.to_path_buf(),
+            line: e.location().map(|location| location.line()),
+            message: e.to_string(),
+        })
+}
+
+/// Reads the object of a file of `directory`, the class of the object being given by the directory
+fn parse_object(directory: &str, path: &Path) -> Result<Resource, DataSourceError> {
+    match directory {
+        "autnum" => parse(path, Resource::Autnum),
+        "domain" => parse(path, Resource::Domain),
+        "inetnum" => parse(path, Resource::Inetnum),
+        "inet6num" => parse(path, Resource::Inet6num),
+        "route" => parse(path, Resource::Route),
+        "route6" => parse(path, Resource::Route6),
+        "as-set" => parse(path, Resource::AsSet),
+        "route-set" => parse(path, Resource::RouteSet),
+        "person" => parse(path, Resource::Person),
+        "role" => parse(path, Resource::Role),
+        "mntner" => parse(path, Resource::Mntner),
+        _ => unreachable!("unknown object directory {}", directory),
+    }
+}
+
+/// Objects of a registry checkout, read again file by file as the checkout changes
+pub struct Registry {
+    path: PathBuf,
+    validation: ValidationMode,
+    /// Objects of the files that can be parsed
+    files: Files,
+    /// Problems of the files that cannot be read or parsed
+    unreadable: BTreeMap<PathBuf, DataSourceError>,
+    /// Objects served since the latest accepted load
+    served: Files,
+}
+
+impl Registry {
+    pub fn new(path: &Path, validation: ValidationMode) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            validation,
+            files: Files::new(),
+            unreadable: BTreeMap::new(),
+            served: Files::new(),
+        }
+    }
+
+    /// Reads the file at `path`, relative to the registry, forgetting it when it was deleted and ignoring the
+    /// files outside of the object directories
+    fn read(&mut self, path: &Path) {
+        let mut components = path.components().map(|component| component.as_os_str().to_string_lossy());
+        let (Some(directory), Some(name), None) = (components.next(), components.next(), components.next()) else {
+            return;
+        };
+        if name.starts_with('.') || !DIRECTORIES.iter().any(|(known, _)| *known == directory) {
+            return;
+        }
+        let path = self.path.join(path);
+        self.files.remove(&path);
+        self.unreadable.remove(&path);
+        if !path.exists() {
+            return;
+        }
+        match parse_object(&directory, &path) {
+            Ok(resource) => {
+                self.files.insert(path, resource);
+            }
+            Err(e) => {
+                self.unreadable.insert(path, e);
+            }
+        }
+    }
+
+    fn read_all(&mut self) {
+        self.files.clear();
+        self.unreadable.clear();
+        for (directory, _) in DIRECTORIES {
+            let Ok(entries) = fs::read_dir(self.path.join(directory)) else {
+                continue;
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                self.read(&Path::new(directory).join(entry.file_name()));
+            }
+        }
+    }
+
+    /// Every problem of the registry: missing directories, unreadable files and objects failing validation
+    pub fn problems(&self) -> Vec<DataSourceError> {
+        let mut problems: Vec<DataSourceError> = DIRECTORIES
+            .iter()
+            .filter(|(directory, required)| *required && !self.path.join(directory).is_dir())
+            .map(|(directory, _)| DataSourceError::MissingDirectory(self.path.join(directory)))
+            .collect();
+        problems.extend(self.unreadable.values().cloned());
+        problems.extend(validate(&self.files).into_iter().map(DataSourceError::Validation));
+        problems
+    }
+
+    /// Serves the valid objects, the invalid ones being logged and left out and those referencing missing objects
+    /// being logged and served, or returns the problems found when the registry cannot be served: in strict mode,
+    /// or when a whole directory is missing
+    fn serve(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>> {
+        let problems = self.problems();
+        // leaving out a missing directory would withdraw every object of its class
+        let missing = problems.iter().any(|e| matches!(e, DataSourceError::MissingDirectory(_)));
+        if (self.validation == ValidationMode::Strict || missing) && !problems.is_empty() {
+            return Err(problems);
+        }
+        for problem in &problems {
+            if problem.is_reference() {
+                warn!("Serving despite {}", problem);
+            } else {
+                warn!("Leaving out {}", problem);
+            }
+        }
+        // an object referencing a missing one is still served, withdrawing it would not bring the other back
+        let invalid: HashSet<&Path> = problems.iter().filter(|e| !e.is_reference()).filter_map(|e| e.file()).collect();
+        let served: Files = self
+            .files
+            .iter()
+            .filter(|(file, _)| !invalid.contains(file.as_path()))
+            .map(|(file, resource)| (file.clone(), resource.clone()))
+            .collect();
+        let mut changes = vec![];
+        for (file, resource) in &self.served {
+            match served.get(file) {
+                None => changes.push(Change::Deleted(file.clone(), resource.clone())),
+                Some(new) if new != resource => changes.push(Change::Modified(file.clone(), Box::new(resource.clone()), new.clone())),
+                Some(_) => {}
+            }
+        }
+        for (file, resource) in &served {
+            if !self.served.contains_key(file) {
+                changes.push(Change::Added(file.clone(), resource.clone()));
+            }
+        }
+        self.served = served;
+        Ok(changes)
+    }
+
+    /// Reads every file of the registry, returning the objects served
+    pub fn load(&mut self) -> Result<Vec<Resource>, Vec<DataSourceError>> {
+        self.read_all();
+        self.serve()?;
+        Ok(self.served.values().cloned().collect())
+    }
+
+    /// Reads again the files at `paths`, relative to the registry, returning how the objects served changed
+    pub fn reload(&mut self, paths: &[PathBuf]) -> Result<Vec<Change>, Vec<DataSourceError>> {
+        for path in paths {
+            self.read(path);
+        }
+        self.serve()
+    }
+
+    /// Reads every file of the registry again, returning how the objects served changed
+    pub fn reload_all(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>> {
+        self.read_all();
+        self.serve()
+    }
+}
+
+/// Every problem of the registry at `registry`, by file
+pub fn lint(registry: &Path) -> Vec<DataSourceError> {
+    let mut registry = Registry::new(registry, ValidationMode::Warn);
+    registry.read_all();
+    let mut problems = registry.problems();
+    problems.sort_by(|a, b| a.file().cmp(&b.file()));
+    problems
+}
@@ -0,0 +1,137 @@
+use crate::config::{Config, SignaturePolicy};
+use crate::datasource::DataSourceError;
+use git2::{Oid, Repository};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
+
+/// Namespace of the SSH signatures made by git
+const SSH_NAMESPACE: &str = "git";
+
+/// Checks that registry commits are signed by trusted keys, using `gpgv` for GPG signatures and `ssh-keygen`
+/// for SSH signatures as git does
+pub struct SignatureVerifier {
+    gpg_keyring: Option<PathBuf>,
+    ssh_signers: Option<PathBuf>,
+    policy: SignaturePolicy,
+}
+
+/// Runs `command` with `input` on stdin, returning its stdout, or its stderr as the error
+fn run(command: &mut Command, input: &[u8]) -> Result<String, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("unable to run {:?}: {}", command.get_program(), e))?;
+    // the command may exit before reading everything, its status telling what happened
+    let _ = child.stdin.take().unwrap().write_all(input);
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+impl SignatureVerifier {
+    /// Verifier of the trusted keys of `config`, or `None` when no key is trusted and commits are not checked
+    pub fn new(config: &Config) -> Option<Self> {
+        if config.trusted_gpg_keyring.is_none() && config.trusted_ssh_signers.is_none() {
+            return None;
+        }
+        Some(Self {
+            gpg_keyring: config.trusted_gpg_keyring.clone(),
+            ssh_signers: config.trusted_ssh_signers.clone(),
+            policy: config.signature_policy,
+        })
+    }
+
+    /// Checks that the tools verifying the trusted kinds of signatures can be run, and that the signatures can be
+    /// written to the temporary directory
+    pub fn check_environment(&self) -> Result<(), String> {
+        let tools = [(&self.gpg_keyring, "gpgv"), (&self.ssh_signers, "ssh-keygen")];
+        for (_, tool) in tools.iter().filter(|(trusted, _)| trusted.is_some()) {
+            Command::new(tool)
+                .arg("--help")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map_err(|e| format!("{} is required to verify signatures: {}", tool, e))?;
+        }
+        NamedTempFile::new()
+            .map(|_| ())
+            .map_err(|e| format!("a writable temporary directory is required to verify signatures: {}", e))
+    }
+
+    /// Checks the commits `new` brings after `old`, the last applied commit, according to the policy, only `new`
+    /// being checked when there is no previous commit
+    pub fn verify_update(&self, repo: &Repository, old: Option<Oid>, new: Oid) -> Result<(), DataSourceError> {
+        if self.policy == SignaturePolicy::Head || old.is_none() {
+            return self.verify(repo, new);
+        }
+        let mut walk = repo.revwalk()?;
+        walk.push(new)?;
+        walk.hide(old.unwrap())?;
+        for oid in walk {
+            self.verify(repo, oid?)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that commit `oid` carries a valid signature from a trusted key
+    pub fn verify(&self, repo: &Repository, oid: Oid) -> Result<(), DataSourceError> {
+        let rejected = |reason: String| DataSourceError::Signature(format!("commit {}: {}", oid, reason));
+        let (signature, data) = repo
+            .extract_signature(&oid, None)
+            .map_err(|_| rejected("not signed".to_string()))?;
+        // created exclusively and only readable by us, so that it cannot be swapped before being verified
+        let mut signature_file = NamedTempFile::new().map_err(|e| rejected(e.to_string()))?;
+        signature_file.write_all(&signature).map_err(|e| rejected(e.to_string()))?;
+        let result = if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+            match &self.ssh_signers {
+                Some(signers) => Self::verify_ssh(signers, signature_file.path(), &data),
+                None => Err("SSH signatures are not trusted".to_string()),
+            }
+        } else {
+            match &self.gpg_keyring {
+                Some(keyring) => Self::verify_gpg(keyring, signature_file.path(), &data),
+                None => Err("GPG signatures are not trusted".to_string()),
+            }
+        };
+        result.map_err(rejected)
+    }
+
+    fn verify_gpg(keyring: &Path, signature_file: &Path, data: &[u8]) -> Result<(), String> {
+        // gpgv looks up keyrings without a slash in its home directory
+        let keyring = fs::canonicalize(keyring).map_err(|e| format!("{}: {}", keyring.display(), e))?;
+        run(
+            Command::new("gpgv").arg("--keyring").arg(keyring).arg(signature_file).arg("-"),
+            data,
+        )
+        .map(|_| ())
+    }
+
+    fn verify_ssh(signers: &Path, signature_file: &Path, data: &[u8]) -> Result<(), String> {
+        let principals = run(
+            Command::new("ssh-keygen")
+                .args(["-Y", "find-principals", "-f"])
+                .arg(signers)
+                .arg("-s")
+                .arg(signature_file),
+            &[],
+        )?;
+        let principal = principals.lines().next().unwrap_or_default();
+        run(
+            Command::new("ssh-keygen")
+                .args(["-Y", "verify", "-n", SSH_NAMESPACE, "-f"])
+                .arg(signers)
+                .args(["-I", principal, "-s"])
+                .arg(signature_file),
+            data,
+        )
+        .map(|_| ())
+    }
+}
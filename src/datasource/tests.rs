@@ -0,0 +1,294 @@
+use super::*;
+use crate::config::{Config, ValidationMode};
+use crate::datasource::directory::DirectoryDataSource;
+use crate::datasource::git::GitDataSource;
+use crate::datasource::registry::{lint, Registry};
+use crate::datasource::signature::SignatureVerifier;
+use clap::Parser;
+
+#[test]
+fn test_git_data_source_changes() {
+    let path = std::env::temp_dir().join(format!("changes-{}", std::process::id()));
+    let (remote_path, checkout_path) = (path.join("remote"), path.join("checkout"));
+    let remote = git2::Repository::init(&remote_path).unwrap();
+    let author = git2::Signature::now("Meow", "meow@catmunch.net").unwrap();
+    let commit = |files: &[(&str, Option<&str>)]| {
+        for (file, content) in files {
+            match content {
+                Some(content) => {
+                    std::fs::create_dir_all(remote_path.join(file).parent().unwrap()).unwrap();
+                    std::fs::write(remote_path.join(file), content).unwrap();
+                }
+                None => std::fs::remove_file(remote_path.join(file)).unwrap(),
+            }
+        }
+        let mut index = remote.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        let tree = remote.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = remote.head().ok().map(|head| head.peel_to_commit().unwrap());
+        remote.commit(Some("HEAD"), &author, &author, "update", &tree, &parent.iter().collect::<Vec<_>>()).unwrap();
+    };
+    commit(&[
+        ("autnum/AS64601", Some("autnum: AS64601\nname: MEOW\n")),
+        ("autnum/AS64602", Some("autnum: AS64602\nname: PURR\n")),
+        ("route/10.1.0.0_16", Some("cidr: 10.1.0.0/16\norigin: [AS64601]\n")),
+        ("domain/.gitkeep", Some("")),
+        ("inetnum/.gitkeep", Some("")),
+        ("inet6num/.gitkeep", Some("")),
+        ("route6/.gitkeep", Some("")),
+        ("README", Some("registry\n")),
+    ]);
+    let branch = remote.head().unwrap().shorthand().unwrap().to_string();
+    let config = Config::parse_from([
+        "dns-whois-server",
+        "--git-repo",
+        remote_path.to_str().unwrap(),
+        "--git-path",
+        checkout_path.to_str().unwrap(),
+        "--git-branch",
+        &branch,
+    ]);
+    let mut source = GitDataSource::new(config);
+    assert!(matches!(source.update(), Ok(UpdateOutcome::Updated(_))));
+    assert_eq!(source.get_resources().unwrap().len(), 3);
+    let serial = source.get_serial().unwrap();
+    commit(&[
+        ("autnum/AS64601", Some("autnum: AS64601\nname: MEOW-NET\n")),
+        ("autnum/AS64602", None),
+        ("autnum/AS64603", Some("autnum: AS64603\nname: NYAN\n")),
+        ("README", Some("cat registry\n")),
+    ]);
+    assert!(matches!(source.update(), Ok(UpdateOutcome::Updated(_))));
+    let changes: Vec<String> = source
+        .get_changes()
+        .unwrap()
+        .iter()
+        .map(|change| change.to_string().replace(&format!("{}/", checkout_path.display()), ""))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            "autnum/AS64601: modified autnum AS64601",
+            "autnum/AS64602: deleted autnum AS64602",
+            "autnum/AS64603: added autnum AS64603",
+        ]
+    );
+    // increasing even for a commit made in the same second
+    assert!(source.get_serial().unwrap() > serial);
+    assert_eq!(source.update(), Ok(UpdateOutcome::Unchanged));
+    assert!(source.get_changes().unwrap().is_empty());
+    std::fs::remove_dir_all(&path).unwrap();
+}
+#[test]
+fn test_directory_data_source() {
+    let registry = std::env::temp_dir().join(format!("directory-{}", std::process::id()));
+    for directory in ["autnum", "domain", "inetnum", "inet6num", "route", "route6"] {
+        std::fs::create_dir_all(registry.join(directory)).unwrap();
+    }
+    std::fs::write(registry.join("autnum/AS64601"), "autnum: AS64601\nname: MEOW\n").unwrap();
+    let registry_path = registry.to_str().unwrap();
+    let config = Config::parse_from(["dns-whois-server", "--source", "directory", "--directory", registry_path]);
+    let mut source = DirectoryDataSource::new(config);
+    assert!(source.get_serial().is_err());
+    assert!(matches!(source.update(), Ok(UpdateOutcome::Updated(_))));
+    let (serial, revision) = (source.get_serial().unwrap(), source.get_revision().unwrap());
+    assert_eq!(source.update(), Ok(UpdateOutcome::Unchanged));
+    // files outside of the object directories and rewrites of the same contents are not changes
+    std::fs::create_dir_all(registry.join("docs")).unwrap();
+    std::fs::write(registry.join("docs/README"), "Meow\n").unwrap();
+    std::fs::write(registry.join("autnum/AS64601"), "autnum: AS64601\nname: MEOW\n").unwrap();
+    assert_eq!(source.update(), Ok(UpdateOutcome::Unchanged));
+    std::fs::write(registry.join("autnum/AS64602"), "autnum: AS64602\nname: PURR\n").unwrap();
+    assert!(matches!(source.update(), Ok(UpdateOutcome::Updated(_))));
+    assert!(source.get_serial().unwrap() > serial);
+    assert_eq!(source.get_resources().unwrap().len(), 2);
+    std::fs::remove_file(registry.join("autnum/AS64602")).unwrap();
+    assert_eq!(source.update(), Ok(UpdateOutcome::Updated(revision)));
+    let changes = source.get_changes().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].to_string(), format!("{}/autnum/AS64602: deleted autnum AS64602", registry_path));
+    std::fs::remove_dir_all(&registry).unwrap();
+    assert!(source.update().is_err());
+}
+#[test]
+fn test_signature_verifier() {
+    let path = std::env::temp_dir().join(format!("signature-{}", std::process::id()));
+    let repo = git2::Repository::init(&path).unwrap();
+    let author = git2::Signature::now("Meow", "meow@catmunch.net").unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    let first = repo.commit(Some("HEAD"), &author, &author, "first", &tree, &[]).unwrap();
+    let parent = repo.find_commit(first).unwrap();
+    let second = repo.commit(Some("HEAD"), &author, &author, "second", &tree, &[&parent]).unwrap();
+    let args = ["dns-whois-server", "--git-repo", "registry", "--trusted-ssh-signers", "allowed_signers"];
+    let verifier = SignatureVerifier::new(&Config::parse_from(args)).unwrap();
+    assert_eq!(
+        verifier.verify_update(&repo, Some(first), second),
+        Err(DataSourceError::Signature(format!("commit {}: not signed", second)))
+    );
+    // only the commits after the last applied one are checked
+    assert_eq!(verifier.verify_update(&repo, Some(second), second), Ok(()));
+    assert!(SignatureVerifier::new(&Config::parse_from(["dns-whois-server", "--git-repo", "registry"])).is_none());
+    std::fs::remove_dir_all(&path).unwrap();
+}
+/// Whether `tools` can all be run, the tests signing commits being skipped otherwise
+fn tools_available(tools: &[&str]) -> bool {
+    let missing: Vec<&str> = tools
+        .iter()
+        .copied()
+        .filter(|tool| std::process::Command::new(tool).arg("--help").output().is_err())
+        .collect();
+    if !missing.is_empty() {
+        eprintln!("skipped, {} not found", missing.join(", "));
+    }
+    missing.is_empty()
+}
+/// Runs `command` with `input` on stdin, returning its stdout
+fn run_tool(command: &mut std::process::Command, input: &str) -> String {
+    use std::io::Write;
+    use std::process::Stdio;
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?} failed", command);
+    String::from_utf8(output.stdout).unwrap()
+}
+/// Commit of an empty tree after `parent`, signed by `sign` from the commit data
+fn signed_commit(repo: &git2::Repository, parent: Option<git2::Oid>, sign: &dyn Fn(&str) -> String) -> git2::Oid {
+    let author = git2::Signature::now("Meow", "meow@catmunch.net").unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    let parent = parent.map(|parent| repo.find_commit(parent).unwrap());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let buffer = repo.commit_create_buffer(&author, &author, "meow", &tree, &parents).unwrap();
+    let data = buffer.as_str().unwrap();
+    repo.commit_signed(data, &sign(data), None).unwrap()
+}
+/// SSH key pair at `path`, and the signer of commits with it
+fn ssh_key(path: &std::path::Path) -> (String, impl Fn(&str) -> String) {
+    let key = path.to_path_buf();
+    run_tool(std::process::Command::new("ssh-keygen").args(["-q", "-t", "ed25519", "-N", "", "-f"]).arg(&key), "");
+    let public_key = std::fs::read_to_string(key.with_extension("pub")).unwrap();
+    let sign = move |data: &str| {
+        run_tool(std::process::Command::new("ssh-keygen").args(["-q", "-Y", "sign", "-n", "git", "-f"]).arg(&key), data)
+    };
+    (public_key, sign)
+}
+#[test]
+fn test_signed_commits() {
+    if !tools_available(&["gpg", "gpgconf", "gpgv", "ssh-keygen"]) {
+        return;
+    }
+    let path = std::env::temp_dir().join(format!("signed-{}", std::process::id()));
+    let repo = git2::Repository::init(path.join("repo")).unwrap();
+    let (trusted_key, trusted) = ssh_key(&path.join("trusted"));
+    let (_, untrusted) = ssh_key(&path.join("untrusted"));
+    let signers = path.join("allowed_signers");
+    std::fs::write(&signers, format!("meow@catmunch.net namespaces=\"git\" {}", trusted_key)).unwrap();
+    // a GPG key in its own home, exported as the trusted keyring
+    let gnupg_home = path.join("gnupg");
+    std::fs::create_dir_all(&gnupg_home).unwrap();
+    let gpg = |args: &[&str], input: &str| {
+        run_tool(std::process::Command::new("gpg").env("GNUPGHOME", &gnupg_home).arg("--batch").args(args), input)
+    };
+    gpg(&["--passphrase", "", "--quick-gen-key", "Meow <meow@catmunch.net>", "ed25519", "sign", "never"], "");
+    let keyring = path.join("keyring.gpg");
+    gpg(&["--output", keyring.to_str().unwrap(), "--export"], "");
+    let gpg_signed = signed_commit(&repo, None, &|data| gpg(&["--armor", "--detach-sign"], data));
+    let _ = std::process::Command::new("gpgconf").env("GNUPGHOME", &gnupg_home).args(["--kill", "gpg-agent"]).status();
+    let first = signed_commit(&repo, Some(gpg_signed), &trusted);
+    let second = signed_commit(&repo, Some(first), &untrusted);
+    let third = signed_commit(&repo, Some(second), &trusted);
+    let verifier = |policy: &str| {
+        let (signers, keyring) = (signers.to_str().unwrap(), keyring.to_str().unwrap());
+        let args = ["dns-whois-server", "--trusted-ssh-signers", signers, "--trusted-gpg-keyring", keyring];
+        SignatureVerifier::new(&Config::parse_from(args.into_iter().chain(["--signature-policy", policy]))).unwrap()
+    };
+    let (all, head) = (verifier("all"), verifier("head"));
+    assert_eq!(all.verify(&repo, gpg_signed), Ok(()));
+    assert_eq!(all.verify(&repo, first), Ok(()));
+    let rejects_second = |result| {
+        matches!(result, Err(DataSourceError::Signature(e)) if e.starts_with(&format!("commit {}", second)))
+    };
+    assert!(rejects_second(all.verify(&repo, second)));
+    // every fetched commit is checked with the all policy, only the new head with the head policy
+    assert!(rejects_second(all.verify_update(&repo, Some(first), third)));
+    assert_eq!(all.verify_update(&repo, Some(second), third), Ok(()));
+    assert_eq!(head.verify_update(&repo, Some(first), third), Ok(()));
+    // keys of a kind are only trusted when configured
+    let args = ["dns-whois-server", "--trusted-ssh-signers", signers.to_str().unwrap()];
+    let ssh_only = SignatureVerifier::new(&Config::parse_from(args)).unwrap();
+    assert_eq!(
+        ssh_only.verify(&repo, gpg_signed),
+        Err(DataSourceError::Signature(format!("commit {}: GPG signatures are not trusted", gpg_signed)))
+    );
+    std::fs::remove_dir_all(&path).unwrap();
+}
+#[test]
+fn test_git_checkout_verification() {
+    if !tools_available(&["ssh-keygen"]) {
+        return;
+    }
+    let path = std::env::temp_dir().join(format!("checkout-{}", std::process::id()));
+    let origin = git2::Repository::init(path.join("origin")).unwrap();
+    let (trusted_key, trusted) = ssh_key(&path.join("trusted"));
+    let signers = path.join("allowed_signers");
+    std::fs::write(&signers, format!("meow@catmunch.net namespaces=\"git\" {}", trusted_key)).unwrap();
+    let author = git2::Signature::now("Meow", "meow@catmunch.net").unwrap();
+    let tree = origin.find_tree(origin.index().unwrap().write_tree().unwrap()).unwrap();
+    let unsigned = origin.commit(Some("refs/heads/main"), &author, &author, "meow", &tree, &[]).unwrap();
+    origin.set_head("refs/heads/main").unwrap();
+    // a checkout of an untrusted commit already there at startup
+    let checkout = path.join("checkout");
+    git2::Repository::clone(path.join("origin").to_str().unwrap(), &checkout).unwrap();
+    let config = Config::parse_from([
+        "dns-whois-server",
+        "--git-repo",
+        path.join("origin").to_str().unwrap(),
+        "--git-path",
+        checkout.to_str().unwrap(),
+        "--trusted-ssh-signers",
+        signers.to_str().unwrap(),
+    ]);
+    let mut source = GitDataSource::new(config);
+    assert!(matches!(source.update(), Err(DataSourceError::Signature(_))));
+    assert!(source.get_resources().is_err());
+    // served once the branch moves to a trusted commit
+    let signed = signed_commit(&origin, Some(unsigned), &trusted);
+    origin.reference("refs/heads/main", signed, true, "").unwrap();
+    assert_eq!(source.update(), Ok(UpdateOutcome::Updated(signed.to_string())));
+    assert_eq!(source.update(), Ok(UpdateOutcome::Unchanged));
+    std::fs::remove_dir_all(&path).unwrap();
+}
+#[test]
+fn test_lint() {
+    let registry = std::env::temp_dir().join(format!("lint-{}", std::process::id()));
+    for directory in ["autnum", "domain", "inetnum", "inet6num", "route", "route6"] {
+        std::fs::create_dir_all(registry.join(directory)).unwrap();
+    }
+    std::fs::write(registry.join("autnum/AS64601"), "autnum: AS64601\nname: MEOW\n").unwrap();
+    std::fs::write(registry.join("autnum/.gitkeep"), "").unwrap();
+    std::fs::write(registry.join("route/10.1.0.0_16"), "cidr: 10.1.0.0/16\norigin: [AS64699]\n").unwrap();
+    std::fs::write(registry.join("inetnum/10.1.0.0_16"), "cidr: 10.1.2.3/16\n").unwrap();
+    let errors = lint(&registry);
+    assert_eq!(errors.len(), 2);
+    match &errors[0] {
+        DataSourceError::Parse { file, line, message } => {
+            assert_eq!(*file, registry.join("inetnum/10.1.0.0_16"));
+            assert_eq!(*line, Some(1));
+            assert!(message.contains("invalid ipv4 cidr 10.1.2.3/16"));
+        }
+        error => panic!("unexpected error {}", error),
+    }
+    assert!(matches!(&errors[1], DataSourceError::Validation(e) if e.file == registry.join("route/10.1.0.0_16")));
+    // the invalid objects are left out but not those referencing missing objects, and the whole registry is
+    // rejected in strict mode
+    let resources = Registry::new(&registry, ValidationMode::Warn).load().unwrap();
+    assert!(matches!(resources.as_slice(), [Resource::Autnum(_), Resource::Route(_)]));
+    assert_eq!(Registry::new(&registry, ValidationMode::Strict).load().unwrap_err(), errors);
+    // a missing directory rejects the registry whatever the mode
+    std::fs::remove_dir_all(registry.join("route6")).unwrap();
+    assert!(Registry::new(&registry, ValidationMode::Warn).load()
+        .unwrap_err()
+        .contains(&DataSourceError::MissingDirectory(registry.join("route6"))));
+    std::fs::remove_dir_all(&registry).unwrap();
+}
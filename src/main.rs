@@ -1,18 +1,21 @@
+use crate::datasource::directory::DirectoryDataSource;
 use crate::datasource::git::GitDataSource;
-use crate::datasource::DataSource;
+use crate::datasource::registry::lint;
+use crate::datasource::{DataSource, DataSourceError, RejectedUpdate, UpdateOutcome, UpdateStatus};
 use crate::service::dns::run_dns_server;
 use crate::service::whois::run_whois_server;
 use crate::store::memory::MemoryStore;
 use crate::store::Store;
-use clap::Parser;
-use config::Config;
-use log::info;
+use config::{Command, Config, SourceKind};
+use log::{error, info, warn};
 use std::io::Error;
 use std::time::Duration;
 use tokio::{select, signal};
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 use crate::service::healthcheck::run_health_check_server;
+use crate::service::rdap::run_rdap_server;
+use crate::service::notify::notify_secondaries;
 
 mod config;
 mod datasource;
@@ -20,15 +23,69 @@ mod resource;
 mod service;
 mod store;
 mod util;
+mod validation;
+
+/// Loads the current revision of `source` into `store`, only applying the changes once the store is loaded,
+/// keeping the previous resources and recording the problems in `status` when the revision cannot be served
+fn load_revision(source: &mut dyn DataSource, store: &mut Box<dyn Store>, status: &UpdateStatus) -> bool {
+    let loaded = source.get_serial().map_err(|e| vec![e]).and_then(|serial| {
+        if store.is_ready() {
+            let changes = source.get_changes()?;
+            for change in &changes {
+                info!("{}", change);
+            }
+            store.apply(&changes, serial);
+        } else {
+            store.set(&source.get_resources()?, serial);
+        }
+        Ok(())
+    });
+    match loaded {
+        Ok(()) => {
+            *status.write().unwrap() = None;
+            true
+        }
+        Err(errors) => {
+            let revision = source.get_revision().unwrap_or_else(|_| "unknown".to_string());
+            error!("Rejected revision {}, keeping the previous registry:", revision);
+            for error in &errors {
+                error!("{}", error);
+            }
+            *status.write().unwrap() = Some(RejectedUpdate {
+                revision,
+                errors: errors.iter().map(|e| e.to_string()).collect(),
+            });
+            false
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     env_logger::init();
-    let config: &'static Config = Box::leak(Box::new(Config::parse()));
-    let mut source: Box<dyn DataSource> = Box::new(GitDataSource::new(config.clone()));
-    source.update();
+    let config: &'static Config = Box::leak(Box::new(Config::parse_args()));
+    if let Some(Command::Lint { path }) = &config.command {
+        let errors = lint(path);
+        for error in &errors {
+            println!("{}", error);
+        }
+        if !errors.is_empty() {
+            println!("{} problems found.", errors.len());
+            std::process::exit(1);
+        }
+        println!("No problems found.");
+        return Ok(());
+    }
+    let mut source: Box<dyn DataSource> = match config.source {
+        SourceKind::Git => Box::new(GitDataSource::new(config.clone())),
+        SourceKind::Directory => Box::new(DirectoryDataSource::new(config.clone())),
+    };
+    if let Err(e) = source.update() {
+        warn!("Update failed: {}", e);
+    }
     let mut store: Box<dyn Store> = Box::new(MemoryStore::new());
-    store.set(&source.get_resources());
+    let status = UpdateStatus::default();
+    load_revision(source.as_mut(), &mut store, &status);
     let mut services = vec![];
     let token = CancellationToken::new();
     let store_copy = store.clone();
@@ -48,7 +105,15 @@ async fn main() -> Result<(), Error> {
     let store_copy = store.clone();
     let token_copy = token.clone();
     services.push(tokio::spawn(async move {
-        run_health_check_server(config, store_copy, token_copy)
+        run_rdap_server(config, store_copy, token_copy)
+            .await
+            .expect("Unable to start RDAP server");
+    }));
+    let store_copy = store.clone();
+    let status_copy = status.clone();
+    let token_copy = token.clone();
+    services.push(tokio::spawn(async move {
+        run_health_check_server(config, store_copy, status_copy, token_copy)
             .await
             .expect("Unable to start health check server")
     }));
@@ -56,12 +121,29 @@ async fn main() -> Result<(), Error> {
     services.push(tokio::spawn(async move {
         loop {
             info!("Checking update...");
-            if source.update() {
-                info!("Updating...");
-                store.set(&source.get_resources());
+            let reload = match source.update() {
+                Ok(UpdateOutcome::Updated(revision)) => {
+                    info!("Updating to {}...", revision);
+                    true
+                }
+                // a rejected revision is loaded again, until it is fixed or replaced
+                Ok(UpdateOutcome::Unchanged) if status.read().unwrap().is_some() => true,
+                Ok(UpdateOutcome::Unchanged) => {
+                    info!("No update available.");
+                    false
+                }
+                Err(e @ DataSourceError::Signature(_)) => {
+                    error!("Rejected update: {}", e);
+                    false
+                }
+                Err(e) => {
+                    warn!("Update failed: {}", e);
+                    false
+                }
+            };
+            if reload && load_revision(source.as_mut(), &mut store, &status) {
                 info!("Updated.");
-            } else {
-                info!("No update available.");
+                tokio::spawn(notify_secondaries(config, store.get_serial()));
             }
             select! {
                 _ = sleep(Duration::from_secs(config.interval)) => {
@@ -84,11 +166,13 @@ async fn main() -> Result<(), Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::resource::contacts::Contacts;
     use crate::resource::inetnum::Inetnum;
     use crate::resource::Resource;
-    use crate::store::Store;
+    use crate::store::Change;
     use crate::util::cidr::Ipv4CidrWrapper;
     use cidr::Ipv4Cidr;
+    use std::path::PathBuf;
     use std::str::FromStr;
     #[test]
     fn test_mem_store() {
@@ -98,19 +182,70 @@ mod tests {
                 cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.1.0.0/16").unwrap()),
                 description: None,
                 ns: Some(Vec::new()),
+                ptr: None,
+                ptr_template: None,
+                contacts: Contacts::default(),
             }),
             Resource::Inetnum(Inetnum {
                 cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.2.0.0/16").unwrap()),
                 description: None,
                 ns: Some(Vec::new()),
+                ptr: None,
+                ptr_template: None,
+                contacts: Contacts::default(),
             }),
-        ]));
+        ]), 1);
         let x = store.get_inetnum_prefixes(Ipv4Cidr::from_str("10.1.2.3/32").unwrap());
         assert_eq!(x.0.len(), 1);
         assert_eq!(x.1.len(), 0);
         assert_eq!(
-            x.0.get(0).unwrap().cidr,
+            x.0.first().unwrap().cidr,
             Ipv4CidrWrapper(Ipv4Cidr::from_str("10.1.0.0/16").unwrap())
         )
     }
+    /// Data source of one revision: its serial and its changes
+    struct StubDataSource(u32, Result<Vec<Change>, Vec<DataSourceError>>);
+    impl DataSource for StubDataSource {
+        fn update(&mut self) -> Result<UpdateOutcome, DataSourceError> {
+            Ok(UpdateOutcome::Updated("c0ffee".to_string()))
+        }
+        fn get_resources(&mut self) -> Result<Vec<Resource>, Vec<DataSourceError>> {
+            self.1.clone().map(|changes| changes.iter().filter_map(Change::added).cloned().collect())
+        }
+        fn get_changes(&mut self) -> Result<Vec<Change>, Vec<DataSourceError>> {
+            self.1.clone()
+        }
+        fn get_serial(&self) -> Result<u32, DataSourceError> {
+            Ok(self.0)
+        }
+        fn get_revision(&self) -> Result<String, DataSourceError> {
+            Ok("c0ffee".to_string())
+        }
+    }
+    #[test]
+    fn test_load_revision() {
+        let mut store: Box<dyn Store> = Box::new(MemoryStore::new());
+        let status = UpdateStatus::default();
+        let autnum = Resource::Autnum(serde_yaml::from_str("autnum: AS64601\nname: MEOW\n").unwrap());
+        let file = PathBuf::from("autnum/AS64601");
+        let added = Change::Added(file.clone(), autnum.clone());
+        assert!(load_revision(&mut StubDataSource(1, Ok(vec![added])), &mut store, &status));
+        let errors = vec![DataSourceError::Parse {
+            file: PathBuf::from("autnum/AS64602"),
+            line: Some(1),
+            message: "invalid type".to_string(),
+        }];
+        assert!(!load_revision(&mut StubDataSource(2, Err(errors)), &mut store, &status));
+        assert_eq!(store.get_serial(), 1);
+        assert!(store.get_autnum("AS64601".to_string()).is_some());
+        let rejected = status.read().unwrap().clone().unwrap();
+        assert_eq!(rejected.revision, "c0ffee");
+        assert_eq!(rejected.errors, ["autnum/AS64602: unable to parse: invalid type"]);
+        // once loaded, the store is only given the changes
+        let deleted = Change::Deleted(file, autnum);
+        assert!(load_revision(&mut StubDataSource(3, Ok(vec![deleted])), &mut store, &status));
+        assert!(status.read().unwrap().is_none());
+        assert_eq!(store.get_serial(), 3);
+        assert!(store.get_autnum("AS64601".to_string()).is_none());
+    }
 }
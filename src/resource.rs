@@ -1,11 +1,21 @@
+use serde::Serialize;
+
+pub mod as_set;
 pub mod autnum;
+pub mod contacts;
 pub mod domain;
 pub mod inet6num;
 pub mod inetnum;
+pub mod mntner;
+pub mod person;
+pub mod role;
 pub mod route;
 pub mod route6;
+pub mod route_set;
 
-#[derive(Debug)]
+/// Serialized as the object itself
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
 pub enum Resource {
     Autnum(autnum::Autnum),
     Domain(domain::Domain),
@@ -13,4 +23,43 @@ pub enum Resource {
     Inet6num(inet6num::Inet6num),
     Route(route::Route),
     Route6(route6::Route6),
+    AsSet(as_set::AsSet),
+    RouteSet(route_set::RouteSet),
+    Person(person::Person),
+    Role(role::Role),
+    Mntner(mntner::Mntner),
+}
+
+impl Resource {
+    /// Object class and primary key, e.g. `autnum AS64601`
+    pub fn name(&self) -> String {
+        match self {
+            Resource::Autnum(autnum) => format!("autnum {}", autnum.autnum),
+            Resource::Domain(domain) => format!("domain {}", domain.domain),
+            Resource::Inetnum(inetnum) => format!("inetnum {}", *inetnum.cidr),
+            Resource::Inet6num(inet6num) => format!("inet6num {}", *inet6num.cidr),
+            Resource::Route(route) => format!("route {}", *route.cidr),
+            Resource::Route6(route6) => format!("route6 {}", *route6.cidr),
+            Resource::AsSet(as_set) => format!("as-set {}", as_set.as_set),
+            Resource::RouteSet(route_set) => format!("route-set {}", route_set.route_set),
+            Resource::Person(person) => format!("person {}", person.nic_hdl),
+            Resource::Role(role) => format!("role {}", role.nic_hdl),
+            Resource::Mntner(mntner) => format!("mntner {}", mntner.mntner),
+        }
+    }
+    pub fn contacts(&self) -> &contacts::Contacts {
+        match self {
+            Resource::Autnum(autnum) => &autnum.contacts,
+            Resource::Domain(domain) => &domain.contacts,
+            Resource::Inetnum(inetnum) => &inetnum.contacts,
+            Resource::Inet6num(inet6num) => &inet6num.contacts,
+            Resource::Route(route) => &route.contacts,
+            Resource::Route6(route6) => &route6.contacts,
+            Resource::AsSet(as_set) => &as_set.contacts,
+            Resource::RouteSet(route_set) => &route_set.contacts,
+            Resource::Person(person) => &person.contacts,
+            Resource::Role(role) => &role.contacts,
+            Resource::Mntner(mntner) => &mntner.contacts,
+        }
+    }
 }
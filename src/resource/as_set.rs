@@ -0,0 +1,47 @@
+use crate::resource::contacts::Contacts;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+
+/// Set of autonomous systems, e.g. the customer cone of an AS (RFC 2622 5.1)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AsSet {
+    pub as_set: String,
+    pub description: Option<String>,
+    /// AS numbers and other as-sets
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
+
+lazy_static! {
+    static ref ASN_REGEX: Regex = Regex::new(r"^(?i)as\d+$").unwrap();
+    static ref AS_SET_NAME_REGEX: Regex = Regex::new(r"^(?i)((as\d+|as-[a-z0-9_-]+):)*as-[a-z0-9_-]+$").unwrap();
+}
+
+/// Whether `name` is an AS number such as AS64601
+pub fn is_asn(name: &str) -> bool {
+    ASN_REGEX.is_match(name)
+}
+
+/// Whether `name` is an as-set name, possibly hierarchical such as AS64601:AS-CUSTOMERS
+pub fn is_as_set_name(name: &str) -> bool {
+    AS_SET_NAME_REGEX.is_match(name)
+}
+
+impl AsSet {
+    /// Checks the name of the set and that its members are AS numbers or as-set names
+    pub fn validate(&self) -> Result<(), SimpleError> {
+        if !is_as_set_name(&self.as_set) {
+            return Err(SimpleError::new(format!("invalid as-set name {}", self.as_set)));
+        }
+        for member in &self.members {
+            if !is_asn(member) && !is_as_set_name(member) {
+                return Err(SimpleError::new(format!("invalid as-set member {}", member)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
+use crate::resource::contacts::Contacts;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Autnum {
     pub autnum: String,
     pub name: String,
     pub description: Option<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
 }
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// References of an object to the handles of its contacts and to its maintainers
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Contacts {
+    /// Person or role handles of the administrative contacts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_c: Vec<String>,
+    /// Person or role handles of the technical contacts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tech_c: Vec<String>,
+    /// Names of the mntners of the object
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mnt_by: Vec<String>,
+}
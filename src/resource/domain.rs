@@ -1,15 +1,177 @@
+use crate::resource::contacts::Contacts;
+use hickory_server::proto::rr::dnssec::rdata::{DNSSECRData, DS as DsRData};
+use hickory_server::proto::rr::dnssec::{Algorithm, DigestType};
+use hickory_server::proto::rr::{rdata, Name, RData, Record, RecordType};
+use hickory_server::proto::serialize::binary::{BinDecoder, Restrict};
 use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Domain {
     pub domain: String,
     pub description: Option<String>,
+    #[serde(default)]
     pub ns: Vec<NS>,
+    pub ds: Option<Vec<DS>>,
+    /// Records served directly by us, only when the domain is not delegated
+    pub records: Option<Records>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct NS {
     pub server: String,
     pub a: Option<std::net::Ipv4Addr>,
     pub aaaa: Option<std::net::Ipv6Addr>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DS {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Records {
+    pub a: Option<Vec<Ipv4Addr>>,
+    pub aaaa: Option<Vec<Ipv6Addr>>,
+    pub cname: Option<String>,
+    pub txt: Option<Vec<String>>,
+    pub mx: Option<Vec<MX>>,
+    pub srv: Option<Vec<Srv>>,
+    pub caa: Option<Vec<Caa>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MX {
+    pub preference: u16,
+    pub exchange: String,
+}
+
+/// Service record at `name` below the domain, e.g. `_sip._tcp`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Srv {
+    pub name: String,
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Caa {
+    #[serde(default)]
+    pub critical: bool,
+    pub tag: String,
+    pub value: String,
+}
+
+/// Longest character-string of a TXT record (RFC 1035 3.3)
+const TXT_STRING_LENGTH: usize = 255;
+
+impl Domain {
+    /// Checks that the inline records are well-formed and only present on a domain without delegation
+    pub fn validate(&self) -> Result<(), SimpleError> {
+        let name = Name::from_str(&self.domain)
+            .map_err(|e| SimpleError::new(format!("invalid domain {}: {}", self.domain, e)))?;
+        if let Some(records) = &self.records {
+            if !self.ns.is_empty() {
+                return Err(SimpleError::new("records are not allowed on a delegated domain"));
+            }
+            records.to_records(&name, 300)?;
+        }
+        for ds in self.ds.iter().flatten() {
+            ds.to_rdata()?;
+        }
+        Ok(())
+    }
+}
+
+impl DS {
+    /// Converts the DS to its record data, the digest type being one we know and the digest hex encoded
+    pub fn to_rdata(&self) -> Result<RData, SimpleError> {
+        let digest_type = DigestType::from_u8(self.digest_type)
+            .map_err(|_| SimpleError::new(format!("unknown ds digest type {}", self.digest_type)))?;
+        let digest = data_encoding::HEXLOWER_PERMISSIVE
+            .decode(self.digest.as_bytes())
+            .map_err(|e| SimpleError::new(format!("invalid ds digest {}: {}", self.digest, e)))?;
+        Ok(RData::DNSSEC(DNSSECRData::DS(DsRData::new(
+            self.key_tag,
+            Algorithm::from_u8(self.algorithm),
+            digest_type,
+            digest,
+        ))))
+    }
+}
+
+impl Records {
+    /// Converts the records to DNS records at `name`, the SRV records at their own names below it
+    pub fn to_records(&self, name: &Name, ttl: u32) -> Result<Vec<Record>, SimpleError> {
+        let parse_name = |value: &str| {
+            Name::from_str(value).map_err(|e| SimpleError::new(format!("invalid name {}: {}", value, e)))
+        };
+        let mut records: Vec<Record> = vec![];
+        if let Some(cname) = &self.cname {
+            // a CNAME cannot coexist with other data (RFC 1034 3.6.2)
+            if self.a.is_some() || self.aaaa.is_some() || self.txt.is_some() || self.mx.is_some() || self.caa.is_some() {
+                return Err(SimpleError::new("cname cannot be combined with other records"));
+            }
+            records.push(Record::from_rdata(name.clone(), ttl, RData::CNAME(rdata::CNAME(parse_name(cname)?))));
+        }
+        for a in self.a.iter().flatten() {
+            records.push(Record::from_rdata(name.clone(), ttl, RData::A(rdata::A(*a))));
+        }
+        for aaaa in self.aaaa.iter().flatten() {
+            records.push(Record::from_rdata(name.clone(), ttl, RData::AAAA(rdata::AAAA(*aaaa))));
+        }
+        if let Some(txt) = &self.txt {
+            if txt.iter().any(|string| string.len() > TXT_STRING_LENGTH) {
+                return Err(SimpleError::new("txt strings are limited to 255 bytes"));
+            }
+            records.push(Record::from_rdata(name.clone(), ttl, RData::TXT(rdata::TXT::new(txt.clone()))));
+        }
+        for mx in self.mx.iter().flatten() {
+            let exchange = parse_name(&mx.exchange)?;
+            records.push(Record::from_rdata(name.clone(), ttl, RData::MX(rdata::MX::new(mx.preference, exchange))));
+        }
+        for srv in self.srv.iter().flatten() {
+            let owner = parse_name(&srv.name)?;
+            if owner.is_fqdn() || owner.iter().any(|label| !label.starts_with(b"_")) {
+                return Err(SimpleError::new(format!("srv name {} must be relative labels starting with '_'", srv.name)));
+            }
+            let owner = owner
+                .append_domain(name)
+                .map_err(|e| SimpleError::new(format!("invalid srv name {}: {}", srv.name, e)))?;
+            let target = parse_name(&srv.target)?;
+            records.push(Record::from_rdata(
+                owner,
+                ttl,
+                RData::SRV(rdata::SRV::new(srv.priority, srv.weight, srv.port, target)),
+            ));
+        }
+        for caa in self.caa.iter().flatten() {
+            records.push(Record::from_rdata(name.clone(), ttl, caa.to_rdata()?));
+        }
+        Ok(records)
+    }
+}
+
+impl Caa {
+    /// Parses the property through its wire format (RFC 8659 4.1), which validates the tag and value
+    fn to_rdata(&self) -> Result<RData, SimpleError> {
+        if self.tag.is_empty() || self.tag.len() > 15 || !self.tag.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SimpleError::new(format!("invalid caa tag {}", self.tag)));
+        }
+        let mut bytes = vec![if self.critical { 0x80 } else { 0 }, self.tag.len() as u8];
+        bytes.extend(self.tag.as_bytes());
+        bytes.extend(self.value.as_bytes());
+        let length = u16::try_from(bytes.len()).map_err(|_| SimpleError::new("caa value is too long"))?;
+        RData::read(&mut BinDecoder::new(&bytes), RecordType::CAA, Restrict::new(length))
+            .map_err(|e| SimpleError::new(format!("invalid caa {} {}: {}", self.tag, self.value, e)))
+    }
+}
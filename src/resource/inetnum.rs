@@ -1,10 +1,32 @@
+use crate::resource::contacts::Contacts;
 use crate::resource::domain::NS;
 use crate::util::cidr::Ipv4CidrWrapper;
+use crate::util::zone::{reverse_name, validate_reverse_names};
 use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Inetnum {
     pub cidr: Ipv4CidrWrapper,
     pub description: Option<String>,
     pub ns: Option<Vec<NS>>,
+    /// Reverse names of single addresses, served by us when the inetnum is not delegated
+    pub ptr: Option<BTreeMap<Ipv4Addr, String>>,
+    /// Reverse name of the addresses missing from `ptr`, e.g. `{ip-dashed}.meow.catmunch`
+    pub ptr_template: Option<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
+
+impl Inetnum {
+    /// The reverse name of `address`, from the map or else the template
+    pub fn ptr(&self, address: Ipv4Addr) -> Option<String> {
+        reverse_name(self.ptr.as_ref(), self.ptr_template.as_deref(), address)
+    }
+    /// Checks that the reverse names are well-formed and only present on an inetnum without delegation
+    pub fn validate(&self) -> Result<(), SimpleError> {
+        validate_reverse_names("inetnum", &*self.cidr, self.ns.is_some(), self.ptr.as_ref(), self.ptr_template.as_deref())
+    }
 }
@@ -0,0 +1,13 @@
+use crate::resource::contacts::Contacts;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Mntner {
+    pub mntner: String,
+    pub description: Option<String>,
+    /// Addresses notified of changes to the maintained objects
+    #[serde(default)]
+    pub email: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
@@ -0,0 +1,14 @@
+use crate::resource::contacts::Contacts;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Person {
+    pub person: String,
+    pub nic_hdl: String,
+    #[serde(default)]
+    pub email: Vec<String>,
+    #[serde(default)]
+    pub phone: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
@@ -0,0 +1,13 @@
+use crate::resource::contacts::Contacts;
+use serde::{Deserialize, Serialize};
+
+/// Contact shared by several persons, e.g. a NOC
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Role {
+    pub role: String,
+    pub nic_hdl: String,
+    #[serde(default)]
+    pub email: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
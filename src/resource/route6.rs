@@ -1,9 +1,12 @@
+use crate::resource::contacts::Contacts;
 use crate::util::cidr::Ipv6CidrWrapper;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Route6 {
     pub cidr: Ipv6CidrWrapper,
     pub description: Option<String>,
     pub origin: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
 }
@@ -0,0 +1,55 @@
+use crate::resource::as_set::{is_as_set_name, is_asn};
+use crate::resource::contacts::Contacts;
+use cidr::IpCidr;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::str::FromStr;
+
+/// Set of prefixes, e.g. those a peer may announce to us (RFC 2622 5.2, RFC 4012 2.2)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RouteSet {
+    pub route_set: String,
+    pub description: Option<String>,
+    /// IPv4 and IPv6 prefixes with an optional range operator (e.g. 10.1.0.0/16^+), other route-sets,
+    /// and AS numbers or as-sets standing for the routes they originate
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+}
+
+lazy_static! {
+    static ref ROUTE_SET_NAME_REGEX: Regex = Regex::new(r"^(?i)((as\d+|rs-[a-z0-9_-]+):)*rs-[a-z0-9_-]+$").unwrap();
+    static ref RANGE_OPERATOR_REGEX: Regex = Regex::new(r"^\^(\+|-|\d+(-\d+)?)$").unwrap();
+}
+
+/// Whether `name` is a route-set name, possibly hierarchical such as AS64601:RS-CUSTOMERS
+pub fn is_route_set_name(name: &str) -> bool {
+    ROUTE_SET_NAME_REGEX.is_match(name)
+}
+
+/// The prefix of a prefix member, without its range operator, when `member` is one
+pub fn member_prefix(member: &str) -> Option<IpCidr> {
+    let (prefix, operator) = member.split_at(member.find('^').unwrap_or(member.len()));
+    if !prefix.contains('/') || (!operator.is_empty() && !RANGE_OPERATOR_REGEX.is_match(operator)) {
+        return None;
+    }
+    IpCidr::from_str(prefix).ok()
+}
+
+impl RouteSet {
+    /// Checks the name of the set and the syntax of its members
+    pub fn validate(&self) -> Result<(), SimpleError> {
+        if !is_route_set_name(&self.route_set) {
+            return Err(SimpleError::new(format!("invalid route-set name {}", self.route_set)));
+        }
+        for member in &self.members {
+            if member_prefix(member).is_none() && !is_route_set_name(member) && !is_asn(member) && !is_as_set_name(member) {
+                return Err(SimpleError::new(format!("invalid route-set member {}", member)));
+            }
+        }
+        Ok(())
+    }
+}
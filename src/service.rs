@@ -1,3 +1,5 @@
 pub mod dns;
 pub mod whois;
 pub mod healthcheck;
+pub mod rdap;
+pub mod notify;
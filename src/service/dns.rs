@@ -1,27 +1,41 @@
 use std::io;
 use crate::config::Config;
-use crate::store::Store;
+use crate::store::{Snapshot, Store};
 use cidr::{Ipv4Cidr, Ipv6Cidr};
-use lazy_static::lazy_static;
 use std::io::Error;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
-use log::info;
-use tokio::net::UdpSocket;
+use std::time::Duration;
+use log::{info, warn};
+use tokio::net::{TcpListener, UdpSocket};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
-use hickory_server::authority::MessageResponseBuilder;
-use hickory_server::proto::op::{Header, MessageType, OpCode, ResponseCode};
-use hickory_server::proto::rr::{IntoName, Name, RData, rdata, Record, LowerName};
-use hickory_server::server::{Request, RequestHandler, ResponseHandler, ResponseInfo};
+use hickory_server::authority::{MessageResponse, MessageResponseBuilder};
+use hickory_server::proto::op::{Edns, Header, Message, MessageType, OpCode, ResponseCode};
+use hickory_server::proto::rr::{DNSClass, IntoName, Name, RData, rdata, Record, LowerName, RecordType};
+use hickory_server::proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use hickory_server::server::{Protocol, Request, RequestHandler, ResponseHandler, ResponseInfo};
 use hickory_server::ServerFuture;
-use crate::resource::domain::NS;
+use crate::resource::domain::{Domain, DS, NS};
+use crate::util::zone::{ipv4_classless_zone, ipv4_reverse_zone, ipv6_reverse_zone, parse_ipv4_classless_label, to_fqdn};
+use cookie::{cookie_well_formed, CookieSigner};
+use dnssec::{nsec_at, nsec_covering, wildcard_of, ZoneSigner};
+use rrl::{Action, RateLimiter, ResponseKind};
 
-pub async fn run_dns_server(config: &Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
-    let handler = Handler::new(store);
+mod cookie;
+mod dnssec;
+mod rrl;
+mod transfer;
+#[cfg(test)]
+mod tests;
+
+pub async fn run_dns_server(config: &'static Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
+    let handler = Handler::new(config, store);
     let mut server = ServerFuture::new(handler);
+    let tcp_timeout = Duration::from_secs(config.dns_tcp_timeout);
     for addr in &config.dns {
         server.register_socket(UdpSocket::bind(addr).await?);
+        server.register_listener(TcpListener::bind(addr).await?, tcp_timeout);
     }
     info!("DNS server started.");
     select! {
@@ -29,25 +43,67 @@ pub async fn run_dns_server(config: &Config, store: Box<dyn Store>, cancellation
             server.shutdown_gracefully().await.unwrap();
         }
         res = server.block_until_done() => {
-            match res {
-                Err(e) => {
-                    panic!("DNS server stopped with error: {}", e);
-                }
-                Ok(()) => {}
+            if let Err(e) = res {
+                panic!("DNS server stopped with error: {}", e);
             }
         }
     }
     info!("DNS server shut down.");
     Ok(())
 }
+enum ZoneKind {
+    Domain,
+    Ipv4,
+    Ipv6,
+}
+struct Zone {
+    name: LowerName,
+    kind: ZoneKind,
+}
 struct Handler {
+    config: &'static Config,
     store: Box<dyn Store>,
+    zones: Vec<Zone>,
+    signer: Option<ZoneSigner>,
+    cookies: CookieSigner,
+    rate_limiter: Option<RateLimiter>,
+}
+/// Largest UDP payload a client without EDNS is guaranteed to accept (RFC 1035 4.2.1)
+const UDP_DEFAULT_PAYLOAD: u16 = 512;
+/// Largest response the client accepts, bounded by our own UDP payload limit (RFC 6891 6.2.5)
+fn max_response_size(request: &Request, udp_payload: u16) -> usize {
+    match request.protocol() {
+        Protocol::Udp => match request.edns() {
+            Some(edns) => edns.max_payload().min(udp_payload).max(UDP_DEFAULT_PAYLOAD) as usize,
+            None => UDP_DEFAULT_PAYLOAD as usize,
+        },
+        _ => u16::MAX as usize,
+    }
+}
+fn response_size(request: &Request, header: &Header, edns: Option<Edns>, answers: &[Record], nameservers: &[Record], additional_records: &[Record]) -> usize {
+    let mut message = Message::new();
+    message.set_header(*header);
+    if let Some(edns) = edns {
+        message.set_edns(edns);
+    }
+    message.add_query(request.query().original().clone());
+    message.insert_answers(answers.to_vec());
+    message.insert_name_servers(nameservers.to_vec());
+    message.insert_additionals(additional_records.to_vec());
+    message.to_vec().map_or(usize::MAX, |bytes| bytes.len())
+}
+/// The COOKIE option of a request
+fn request_cookie(request: &Request) -> Option<&[u8]> {
+    match request.edns()?.option(EdnsCode::Cookie)? {
+        EdnsOption::Unknown(_, cookie) => Some(cookie),
+        _ => None,
+    }
 }
 fn convert_name_to_vec(name: &LowerName) -> Vec<String> {
     name.into_name()
         .unwrap()
         .iter()
-        .map(|v| String::from_utf8_lossy(&v.to_vec()).to_string())
+        .map(|v| String::from_utf8_lossy(v).to_string())
         .collect()
 }
 fn append_ns_record(domain_name: &Name, ns_record: &NS, nameservers: &mut Vec<Record>, additional_records: &mut Vec<Record>) {
@@ -57,29 +113,181 @@ fn append_ns_record(domain_name: &Name, ns_record: &NS, nameservers: &mut Vec<Re
         300,
         RData::NS(rdata::NS(ns_record.server.parse().unwrap())),
     ));
-    if ns_record.a.is_some() {
+    if let Some(a) = ns_record.a {
         additional_records.push(Record::from_rdata(
             ns_name.clone(),
             300,
-            RData::A(rdata::A(ns_record.a.unwrap())),
+            RData::A(rdata::A(a)),
         ));
     }
-    if ns_record.aaaa.is_some() {
+    if let Some(aaaa) = ns_record.aaaa {
         additional_records.push(Record::from_rdata(
             ns_name.clone(),
             300,
-            RData::AAAA(rdata::AAAA(ns_record.aaaa.unwrap())),
+            RData::AAAA(rdata::AAAA(aaaa)),
         ));
     }
 }
-fn append_ns_records(domain_name: &Name, ns_records: &Vec<NS>, nameservers: &mut Vec<Record>, additional_records: &mut Vec<Record>){
+fn append_ns_records(domain_name: &Name, ns_records: &[NS], nameservers: &mut Vec<Record>, additional_records: &mut Vec<Record>){
     ns_records.iter().for_each(|ns| {
         append_ns_record(domain_name, ns, nameservers, additional_records);
     });
 }
 impl Handler {
-    fn new(store: Box<dyn Store>) -> Self {
-        Self { store }
+    fn new(config: &'static Config, store: Box<dyn Store>) -> Self {
+        let mut zones: Vec<Zone> = vec![];
+        for zone in &config.zones {
+            zones.push(Zone {
+                name: LowerName::from_str(to_fqdn(zone).as_str()).unwrap(),
+                kind: ZoneKind::Domain,
+            });
+        }
+        for cidr in &config.rdns_ipv4 {
+            zones.push(Zone {
+                name: LowerName::from_str(ipv4_reverse_zone(cidr).as_str()).unwrap(),
+                kind: ZoneKind::Ipv4,
+            });
+        }
+        for cidr in &config.rdns_ipv6 {
+            zones.push(Zone {
+                name: LowerName::from_str(ipv6_reverse_zone(cidr).as_str()).unwrap(),
+                kind: ZoneKind::Ipv6,
+            });
+        }
+        let signer = config.dnssec_key.as_ref().map(|path| {
+            let signer = ZoneSigner::load(path, config.dnssec_algorithm, config.dnssec_signature_validity);
+            for zone in &zones {
+                info!("DNSSEC DS of {}: {}", zone.name, signer.ds_record(&zone.name.clone().into(), 300));
+            }
+            signer
+        });
+        let cookies = CookieSigner::new(config.dns_cookie_secret.as_deref());
+        let rate_limiter = RateLimiter::new(config);
+        Self {
+            config,
+            store,
+            zones,
+            signer,
+            cookies,
+            rate_limiter,
+        }
+    }
+    /// The served zone `name` belongs to
+    fn zone_of(&self, name: &LowerName) -> Option<&Zone> {
+        self.zones
+            .iter()
+            .filter(|zone| zone.name.zone_of(name))
+            .max_by_key(|zone| zone.name.num_labels())
+    }
+    /// Whether the response is to be signed, i.e. signing is enabled and the client set the DO bit
+    fn dnssec_ok(&self, request: &Request) -> bool {
+        self.signer.is_some() && request.edns().is_some_and(|edns| edns.dnssec_ok())
+    }
+    /// Whether the client presented a valid server cookie, i.e. its source address is not spoofed
+    fn has_valid_cookie(&self, request: &Request) -> bool {
+        request_cookie(request).is_some_and(|cookie| self.cookies.verify(cookie, request.src().ip()))
+    }
+    /// Sends a response unless the client is rate limited, only UDP clients without a valid cookie
+    /// being accounted as their address may be spoofed to use us for amplification
+    async fn send_response<'a, R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> Result<ResponseInfo, Error> {
+        let src = request.src().ip();
+        let action = match &self.rate_limiter {
+            Some(rate_limiter)
+                if matches!(request.protocol(), Protocol::Udp)
+                    && !self.config.rrl_exempt.iter().any(|cidr| cidr.contains(&src))
+                    && !self.has_valid_cookie(request) =>
+            {
+                rate_limiter.check(src, ResponseKind::of(response.header()))
+            }
+            _ => Action::Send,
+        };
+        match action {
+            Action::Send => response_handle.send_response(response).await,
+            Action::Drop => Ok((*response.header()).into()),
+            Action::Slip => {
+                let mut header = *response.header();
+                header.set_truncated(true);
+                let mut builder = MessageResponseBuilder::from_message_request(request);
+                if let Some(edns) = self.response_edns(request) {
+                    builder.edns(edns);
+                }
+                response_handle.send_response(builder.build_no_records(header)).await
+            }
+        }
+    }
+    /// The OPT of the response: our payload limit, the node name when NSID is asked (RFC 5001)
+    /// and a fresh server cookie when the client sent one (RFC 7873)
+    fn response_edns(&self, request: &Request) -> Option<Edns> {
+        request.edns().map(|edns| {
+            let mut response_edns = Edns::new();
+            response_edns.set_max_payload(self.config.dns_udp_payload.max(UDP_DEFAULT_PAYLOAD));
+            response_edns.set_dnssec_ok(self.dnssec_ok(request));
+            if edns.option(EdnsCode::NSID).is_some() {
+                let nsid = self.config.node_name.as_bytes().to_vec();
+                response_edns.options_mut().insert(EdnsOption::Unknown(EdnsCode::NSID.into(), nsid));
+            }
+            if let Some(cookie) = request_cookie(request).filter(|cookie| cookie_well_formed(cookie)) {
+                let cookie = self.cookies.response_cookie(cookie, request.src().ip());
+                response_edns.options_mut().insert(EdnsOption::Unknown(EdnsCode::Cookie.into(), cookie));
+            }
+            response_edns
+        })
+    }
+    fn apex_types(&self) -> Vec<RecordType> {
+        let mut types = vec![RecordType::NS, RecordType::SOA];
+        if self.signer.is_some() {
+            types.push(RecordType::DNSKEY);
+        }
+        types
+    }
+    fn soa_record(&self, zone: &LowerName) -> Record {
+        self.soa_record_at(zone, self.store.get_serial())
+    }
+    fn soa_record_at(&self, zone: &LowerName, serial: u32) -> Record {
+        let config = self.config;
+        Record::from_rdata(
+            zone.into(),
+            config.soa_minimum,
+            RData::SOA(rdata::SOA::new(
+                Name::from_str(config.soa_mname.as_str()).unwrap(),
+                Name::from_str(config.soa_rname.as_str()).unwrap(),
+                serial,
+                config.soa_refresh,
+                config.soa_retry,
+                config.soa_expire,
+                config.soa_minimum,
+            )),
+        )
+    }
+    fn apex_ns_records(&self, zone: &LowerName) -> Vec<Record> {
+        let config = self.config;
+        let servers = if config.apex_ns.is_empty() {
+            std::slice::from_ref(&config.soa_mname)
+        } else {
+            config.apex_ns.as_slice()
+        };
+        servers
+            .iter()
+            .map(|server| {
+                Record::from_rdata(
+                    zone.into(),
+                    300,
+                    RData::NS(rdata::NS(Name::from_str(server.as_str()).unwrap())),
+                )
+            })
+            .collect()
     }
     async fn do_handle_request_code<R: ResponseHandler>(
         &self,
@@ -87,40 +295,313 @@ impl Handler {
         response_handle: &mut R,
         code: ResponseCode,
     ) -> Result<ResponseInfo, Error> {
-        let builder = MessageResponseBuilder::from_message_request(request);
+        let mut builder = MessageResponseBuilder::from_message_request(request);
+        if let Some(edns) = self.response_edns(request) {
+            builder.edns(edns);
+        }
         let mut header = Header::response_from_request(request.header());
         header.set_response_code(code);
         let response = builder.build_no_records(header);
-        response_handle.send_response(response).await
+        self.send_response(request, response_handle, response).await
+    }
+    async fn do_handle_request_negative<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        code: ResponseCode,
+    ) -> Result<ResponseInfo, Error> {
+        let types = if request.query().name() == zone {
+            self.apex_types()
+        } else {
+            vec![]
+        };
+        self.do_send_denial(request, response_handle, zone, code, types)
+            .await
+    }
+    /// Answers authoritatively with no records, carrying the zone SOA for negative caching (RFC 2308)
+    /// and the NSEC records proving the denial when signing, `types` being those existing at the name
+    async fn do_send_denial<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        code: ResponseCode,
+        types: Vec<RecordType>,
+    ) -> Result<ResponseInfo, Error> {
+        let mut header = Header::response_from_request(request.header());
+        header.set_response_code(code);
+        header.set_authoritative(true);
+        let mut nameservers = vec![self.soa_record(zone)];
+        if self.dnssec_ok(request) {
+            let name: Name = request.query().name().into();
+            let ttl = self.config.soa_minimum;
+            if code == ResponseCode::NXDomain {
+                // the parent is taken as the closest encloser, the ranges must not cover any existing name
+                nameservers.push(nsec_covering(&name, ttl));
+                nameservers.push(nsec_covering(&wildcard_of(&name.base_name()), ttl));
+            } else {
+                nameservers.push(nsec_at(&name, types, ttl));
+            }
+        }
+        self.do_send_records(request, response_handle, header, &[], &nameservers, &[])
+            .await
+    }
+    /// Records at a delegation point proving whether it is secure (DS) or not (NSEC without DS)
+    fn delegation_records(&self, request: &Request, name: &Name, ds_records: &[DS]) -> Vec<Record> {
+        if !self.dnssec_ok(request) {
+            return vec![];
+        }
+        if ds_records.is_empty() {
+            return vec![nsec_at(name, vec![RecordType::NS], self.config.soa_minimum)];
+        }
+        // the DS of served domains are validated, none is expected to be left out here
+        ds_records
+            .iter()
+            .filter_map(|ds| ds.to_rdata().ok())
+            .map(|rdata| Record::from_rdata(name.clone(), 300, rdata))
+            .collect()
+    }
+    /// Returns `records` with the RRSIGs of its authoritative RRsets, delegation NS RRsets are not signed
+    fn with_signatures(&self, request: &Request, records: &[Record]) -> Vec<Record> {
+        let mut signed = records.to_vec();
+        if let (Some(signer), Some(zone)) = (&self.signer, self.zone_of(request.query().name())) {
+            let authoritative: Vec<Record> = records
+                .iter()
+                .filter(|record| record.record_type() != RecordType::NS || zone.name == LowerName::from(record.name()))
+                .cloned()
+                .collect();
+            signed.extend(signer.sign(&zone.name.clone().into(), &authoritative));
+        }
+        signed
+    }
+    async fn do_send_records<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        mut header: Header,
+        answers: &[Record],
+        nameservers: &[Record],
+        additional_records: &[Record],
+    ) -> Result<ResponseInfo, Error> {
+        let (answers, nameservers) = if self.dnssec_ok(request) {
+            (self.with_signatures(request, answers), self.with_signatures(request, nameservers))
+        } else {
+            (answers.to_vec(), nameservers.to_vec())
+        };
+        let mut builder = MessageResponseBuilder::from_message_request(request);
+        let edns = self.response_edns(request);
+        if let Some(edns) = edns.clone() {
+            builder.edns(edns);
+        }
+        if matches!(request.protocol(), Protocol::Udp)
+            && response_size(request, &header, edns, &answers, &nameservers, additional_records)
+                > max_response_size(request, self.config.dns_udp_payload)
+        {
+            // the whole answer does not fit, ask the client to retry over TCP
+            header.set_truncated(true);
+            let response = builder.build_no_records(header);
+            return self.send_response(request, response_handle, response).await;
+        }
+        let response = builder.build(
+            header,
+            answers.iter(),
+            nameservers.iter(),
+            &[],
+            additional_records.iter(),
+        );
+        self.send_response(request, response_handle, response).await
     }
 
+    /// Answers the CHAOS TXT queries identifying the node, like the `whoami` WHOIS query
+    async fn do_handle_request_chaos<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+    ) -> Result<ResponseInfo, Error> {
+        let name = request.query().name();
+        let identity_names = [LowerName::from_str("id.server.").unwrap(), LowerName::from_str("hostname.bind.").unwrap()];
+        if !identity_names.contains(name) {
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::Refused)
+                .await;
+        }
+        let mut answers: Vec<Record> = vec![];
+        if matches!(request.query().query_type(), RecordType::TXT | RecordType::ANY) {
+            let mut record = Record::from_rdata(
+                name.into(),
+                0,
+                RData::TXT(rdata::TXT::new(vec![self.config.node_name.clone()])),
+            );
+            record.set_dns_class(DNSClass::CH);
+            answers.push(record);
+        }
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+        self.do_send_records(request, response_handle, header, &answers, &[], &[])
+            .await
+    }
+    async fn do_handle_request_apex<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+    ) -> Result<ResponseInfo, Error> {
+        let answers = match request.query().query_type() {
+            RecordType::SOA => vec![self.soa_record(zone)],
+            RecordType::NS => self.apex_ns_records(zone),
+            RecordType::DNSKEY if self.signer.is_some() => {
+                vec![self.signer.as_ref().unwrap().dnskey_record(&zone.into(), 300)]
+            }
+            _ => {
+                return self
+                    .do_handle_request_negative(request, response_handle, zone, ResponseCode::NoError)
+                    .await
+            }
+        };
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+        self.do_send_records(request, response_handle, header, &answers, &[], &[])
+            .await
+    }
+    /// Answers a name at or below the zone cut `cut`: the DS RRset at the cut and in-bailiwick glue are
+    /// answered authoritatively, anything else is referred to the child zone (RFC 1034 4.3.2)
+    async fn do_handle_request_delegation<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        cut: &Name,
+        ns_records: &[NS],
+        ds_records: &[DS],
+    ) -> Result<ResponseInfo, Error> {
+        let name = request.query().name();
+        let query_type = request.query().query_type();
+        let mut header = Header::response_from_request(request.header());
+        let mut nameservers: Vec<Record> = vec![];
+        let mut additional_records: Vec<Record> = vec![];
+        append_ns_records(cut, ns_records, &mut nameservers, &mut additional_records);
+        if *name == LowerName::from(cut) && query_type == RecordType::DS {
+            if ds_records.is_empty() {
+                return self
+                    .do_send_denial(request, response_handle, zone, ResponseCode::NoError, vec![RecordType::NS])
+                    .await;
+            }
+            let answers: Vec<Record> = ds_records
+                .iter()
+                .filter_map(|ds| ds.to_rdata().ok())
+                .map(|rdata| Record::from_rdata(cut.clone(), 300, rdata))
+                .collect();
+            header.set_authoritative(true);
+            return self
+                .do_send_records(request, response_handle, header, &answers, &[], &[])
+                .await;
+        }
+        // glue belongs to the child zone and cannot carry our signatures, so signed responses refer instead
+        let glue: Vec<Record> = additional_records
+            .iter()
+            .filter(|record| LowerName::from(record.name()) == *name && record.record_type() == query_type)
+            .cloned()
+            .collect();
+        if !glue.is_empty() && !self.dnssec_ok(request) {
+            header.set_authoritative(true);
+            return self
+                .do_send_records(request, response_handle, header, &glue, &[], &[])
+                .await;
+        }
+        nameservers.extend(self.delegation_records(request, cut, ds_records));
+        self.do_send_records(request, response_handle, header, &[], &nameservers, &additional_records)
+            .await
+    }
+    /// Answers a name at or below a domain that is not delegated from its inline records
+    async fn do_handle_request_inline<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        domain_name: &Name,
+        domain: &Domain,
+    ) -> Result<ResponseInfo, Error> {
+        let name = request.query().name();
+        let query_type = request.query().query_type();
+        // validated when loaded
+        let records = domain
+            .records
+            .as_ref()
+            .map(|records| records.to_records(domain_name, 300).unwrap())
+            .unwrap_or_default();
+        let (at_name, below_name): (Vec<Record>, Vec<Record>) = records
+            .into_iter()
+            .filter(|record| name.zone_of(&record.name().into()))
+            .partition(|record| LowerName::from(record.name()) == *name);
+        if at_name.is_empty() {
+            let mut domain_str = name.to_string();
+            domain_str.pop(); // remove the '.'
+            // the domain itself and the empty non-terminals above SRV records and registered domains exist
+            let code = if *name == LowerName::from(domain_name)
+                || !below_name.is_empty()
+                || self.store.has_domain_descendants(domain_str)
+            {
+                ResponseCode::NoError
+            } else {
+                ResponseCode::NXDomain
+            };
+            return self
+                .do_handle_request_negative(request, response_handle, zone, code)
+                .await;
+        }
+        // a CNAME answers every type but its own (RFC 1034 3.6.2)
+        let answers: Vec<Record> = at_name
+            .iter()
+            .filter(|record| record.record_type() == query_type || record.record_type() == RecordType::CNAME)
+            .cloned()
+            .collect();
+        if answers.is_empty() {
+            let mut types: Vec<RecordType> = at_name.iter().map(|record| record.record_type()).collect();
+            types.dedup();
+            return self
+                .do_send_denial(request, response_handle, zone, ResponseCode::NoError, types)
+                .await;
+        }
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+        self.do_send_records(request, response_handle, header, &answers, &[], &[])
+            .await
+    }
     async fn do_handle_request_domain<R: ResponseHandler>(
         &self,
         request: &Request,
         response_handle: &mut R,
+        zone: &LowerName,
     ) -> Result<ResponseInfo, Error> {
         let name = request.query().name();
-        let builder = MessageResponseBuilder::from_message_request(request);
-        let header = Header::response_from_request(request.header());
-        let domain_name = name.into_name().unwrap().trim_to(2);
-        let mut domain_str = domain_name.to_string();
+        let mut domain_str = name.to_string();
         domain_str.pop(); // remove the '.'
-        match self.store.get_domain(domain_str) {
-            Some(domain) => {
-                let mut nameservers: Vec<Record> = vec![];
-                let mut additional_records: Vec<Record> = vec![];
-                append_ns_records(&domain_name, &domain.ns, &mut nameservers, &mut additional_records);
-                let response = builder.build(
-                    header,
-                    &[],
-                    nameservers.iter(),
-                    &[],
-                    additional_records.iter(),
-                );
-                response_handle.send_response(response).await
+        // the longest registered suffix below the zone apex is the delegation point
+        let domain = self
+            .store
+            .get_domain_suffixes(domain_str.clone())
+            .into_iter()
+            .rev()
+            .map(|domain| (Name::from_str(to_fqdn(domain.domain.as_str()).as_str()).unwrap(), domain))
+            .find(|(domain_name, _)| domain_name.num_labels() > zone.num_labels() && zone.zone_of(&domain_name.into()));
+        match domain {
+            Some((domain_name, domain)) if domain.ns.is_empty() => {
+                self.do_handle_request_inline(request, response_handle, zone, &domain_name, &domain)
+                    .await
+            }
+            Some((domain_name, domain)) => {
+                let ds_records = domain.ds.as_deref().unwrap_or_default();
+                self.do_handle_request_delegation(request, response_handle, zone, &domain_name, &domain.ns, ds_records)
+                    .await
+            }
+            // an empty non-terminal above registered domains
+            None if self.store.has_domain_descendants(domain_str) => {
+                self.do_handle_request_negative(request, response_handle, zone, ResponseCode::NoError)
+                    .await
             }
             None => {
-                self.do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                self.do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                     .await
             }
         }
@@ -129,17 +610,22 @@ impl Handler {
         &self,
         request: &Request,
         response_handle: &mut R,
+        zone: &LowerName,
     ) -> Result<ResponseInfo, Error> {
         let name = request.query().name();
-        let builder = MessageResponseBuilder::from_message_request(request);
         let header = Header::response_from_request(request.header());
         let mut parts = convert_name_to_vec(name);
         parts.pop(); // pop arpa
         parts.pop(); // pop in-addr
-        if parts.len() < 1 || parts.len() > 4 {
+        if parts.len() >= 4 && parts[parts.len() - 4].contains('-') {
+            return self
+                .do_handle_request_ipv4_classless(request, response_handle, zone, &parts)
+                .await;
+        }
+        if parts.is_empty() || parts.len() > 4 {
             // invalid length
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                 .await;
         }
         let mut digits: u32 = 0;
@@ -148,7 +634,7 @@ impl Handler {
                 Ok(num) => digits = (digits << 8) + num as u32,
                 Err(_) => {
                     return self
-                        .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                        .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                         .await
                 }
             }
@@ -161,52 +647,114 @@ impl Handler {
         let cidr = Ipv4Cidr::new(Ipv4Addr::from(digits), mask_len);
         if cidr.is_err() {
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                 .await;
         }
         let cidr = cidr.unwrap();
         let (prefixes, _) = self.store.get_inetnum_prefixes(cidr);
-        if prefixes.len() == 0 {
+        if prefixes.is_empty() {
+            // an empty non-terminal above registered inetnums
+            let code = if self.store.has_inetnum_descendants(cidr) {
+                ResponseCode::NoError
+            } else {
+                ResponseCode::NXDomain
+            };
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, code)
                 .await;
         }
-        let mut nameservers: Vec<Record> = vec![];
-        let mut additional_records: Vec<Record> = vec![];
-        match &prefixes[0].ns {
-            Some(ns_records) => {
+        // the outermost delegated prefix, the more specific ones belong to its child zone
+        match prefixes.iter().find_map(|prefix| prefix.ns.as_ref().map(|ns| (prefix, ns))) {
+            Some((prefix, _)) if prefix.cidr.network_length() > 24 => {
+                // the address is an alias into the classless child zone (RFC 2317)
+                let target = Name::from_str(format!("{}.{}", parts[0], ipv4_classless_zone(&prefix.cidr)).as_str()).unwrap();
+                let answers = vec![Record::from_rdata(
+                    name.into(),
+                    300,
+                    RData::CNAME(rdata::CNAME(target)),
+                )];
+                let mut header = header;
+                header.set_authoritative(true);
+                return self
+                    .do_send_records(request, response_handle, header, &answers, &[], &[])
+                    .await;
+            }
+            Some((prefix, ns_records)) => {
                 let cidr_domain_name = name
                     .into_name()
                     .unwrap()
-                    .trim_to(((prefixes[0].cidr.network_length() + 7) / 8 + 2) as usize);
-                append_ns_records(&cidr_domain_name, ns_records, &mut nameservers, &mut additional_records);
+                    .trim_to((prefix.cidr.network_length().div_ceil(8) + 2) as usize);
+                self.do_handle_request_delegation(request, response_handle, zone, &cidr_domain_name, ns_records, &[])
+                    .await
+            }
+            None => {
+                // the most specific inetnum naming the address
+                let ptr = (cidr.network_length() == 32)
+                    .then(|| prefixes.iter().rev().find_map(|prefix| prefix.ptr(cidr.first_address())))
+                    .flatten();
+                self.do_handle_request_ptr(request, response_handle, zone, ptr)
+                    .await
+            }
+        }
+    }
+    /// Answers names in an RFC 2317 classless child zone, e.g. `65.64-127.2.1.10.in-addr.arpa`,
+    /// with a referral when a delegated inetnum matches the zone
+    async fn do_handle_request_ipv4_classless<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        parts: &[String],
+    ) -> Result<ResponseInfo, Error> {
+        let network: Vec<u8> = parts[parts.len() - 3..]
+            .iter()
+            .rev()
+            .filter_map(|part| part.parse::<u8>().ok())
+            .collect();
+        let cidr = match network[..] {
+            [a, b, c] => parse_ipv4_classless_label(&parts[parts.len() - 4], [a, b, c]),
+            _ => None,
+        };
+        let prefix = cidr.and_then(|cidr| {
+            let (prefixes, _) = self.store.get_inetnum_prefixes(cidr);
+            prefixes
+                .into_iter()
+                .find(|prefix| prefix.ns.is_some())
+                .filter(|prefix| *prefix.cidr == cidr)
+        });
+        match prefix {
+            Some(prefix) => {
+                let classless_name = Name::from_str(ipv4_classless_zone(&prefix.cidr).as_str()).unwrap();
+                self.do_handle_request_delegation(
+                    request,
+                    response_handle,
+                    zone,
+                    &classless_name,
+                    prefix.ns.as_ref().unwrap(),
+                    &[],
+                )
+                .await
+            }
+            None => {
+                self.do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
+                    .await
             }
-            _ => {}
         }
-        let response = builder.build(
-            header,
-            &[],
-            nameservers.iter(),
-            &[],
-            additional_records.iter(),
-        );
-        response_handle.send_response(response).await
     }
     async fn do_handle_request_ipv6<R: ResponseHandler>(
         &self,
         request: &Request,
         response_handle: &mut R,
+        zone: &LowerName,
     ) -> Result<ResponseInfo, Error> {
         let name = request.query().name();
-        let builder = MessageResponseBuilder::from_message_request(request);
-        let header = Header::response_from_request(request.header());
         let mut parts = convert_name_to_vec(name);
         parts.pop(); // pop arpa
         parts.pop(); // pop ip6
-        if parts.len() < 1 || parts.len() > 32 {
+        if parts.is_empty() || parts.len() > 32 {
             // invalid length
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                 .await;
         }
         let mut digits: u128 = 0;
@@ -215,9 +763,10 @@ impl Handler {
                 Ok(num) => {
                     if num >= 16 {
                         return self
-                            .do_handle_request_code(
+                            .do_handle_request_negative(
                                 request,
                                 response_handle,
+                                zone,
                                 ResponseCode::NXDomain,
                             )
                             .await;
@@ -226,7 +775,7 @@ impl Handler {
                 }
                 Err(_) => {
                     return self
-                        .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                        .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                         .await
                 }
             }
@@ -239,47 +788,169 @@ impl Handler {
         let cidr = Ipv6Cidr::new(Ipv6Addr::from(digits), mask_len);
         if cidr.is_err() {
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, ResponseCode::NXDomain)
                 .await;
         }
         let cidr = cidr.unwrap();
         let (prefixes, _) = self.store.get_inet6num_prefixes(cidr);
-        if prefixes.len() == 0 {
+        if prefixes.is_empty() {
+            // an empty non-terminal above registered inet6nums
+            let code = if self.store.has_inet6num_descendants(cidr) {
+                ResponseCode::NoError
+            } else {
+                ResponseCode::NXDomain
+            };
             return self
-                .do_handle_request_code(request, response_handle, ResponseCode::NXDomain)
+                .do_handle_request_negative(request, response_handle, zone, code)
                 .await;
         }
-        let mut nameservers: Vec<Record> = vec![];
-        let mut additional_records: Vec<Record> = vec![];
-        match &prefixes[0].ns {
-            Some(ns_records) => {
+        // the outermost delegated prefix, delegated at every nibble-aligned subnet it covers
+        match prefixes.iter().find_map(|prefix| prefix.ns.as_ref().map(|ns| (prefix, ns))) {
+            Some((prefix, ns_records)) => {
                 let cidr_domain_name = name
                     .into_name()
                     .unwrap()
-                    .trim_to(((prefixes[0].cidr.network_length() + 3) / 4 + 2) as usize);
-                append_ns_records(&cidr_domain_name, ns_records, &mut nameservers, &mut additional_records);
+                    .trim_to((prefix.cidr.network_length().div_ceil(4) + 2) as usize);
+                self.do_handle_request_delegation(request, response_handle, zone, &cidr_domain_name, ns_records, &[])
+                    .await
+            }
+            None => {
+                // the most specific inet6num naming the address
+                let ptr = (cidr.network_length() == 128)
+                    .then(|| prefixes.iter().rev().find_map(|prefix| prefix.ptr(cidr.first_address())))
+                    .flatten();
+                self.do_handle_request_ptr(request, response_handle, zone, ptr)
+                    .await
             }
-            _ => {}
         }
-        let response = builder.build(
-            header,
-            &[],
-            nameservers.iter(),
-            &[],
-            additional_records.iter(),
-        );
-        response_handle.send_response(response).await
     }
-    async fn do_handle_request<R: ResponseHandler>(
+    /// Answers the reverse name of an address in a registered prefix without delegation
+    async fn do_handle_request_ptr<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        zone: &LowerName,
+        ptr: Option<String>,
+    ) -> Result<ResponseInfo, Error> {
+        match ptr {
+            Some(ptr) if request.query().query_type() == RecordType::PTR => {
+                let answers = vec![Record::from_rdata(
+                    request.query().name().into(),
+                    300,
+                    RData::PTR(rdata::PTR(Name::from_str(ptr.as_str()).unwrap())),
+                )];
+                let mut header = Header::response_from_request(request.header());
+                header.set_authoritative(true);
+                self.do_send_records(request, response_handle, header, &answers, &[], &[])
+                    .await
+            }
+            Some(_) => {
+                self.do_send_denial(request, response_handle, zone, ResponseCode::NoError, vec![RecordType::PTR])
+                    .await
+            }
+            None => {
+                self.do_handle_request_negative(request, response_handle, zone, ResponseCode::NoError)
+                    .await
+            }
+        }
+    }
+    /// Zone content of a snapshot, without the SOA
+    fn zone_records(&self, zone: &Zone, snapshot: &Snapshot) -> Vec<Record> {
+        let mut records = self.apex_ns_records(&zone.name);
+        records.extend(transfer::resource_records(zone, snapshot.resources.iter().map(|resource| resource.as_ref())));
+        records
+    }
+    /// Answers AXFR with the whole zone, and IXFR with the differences since the client serial (RFC 1995)
+    /// when it is still in the store snapshots, unless the zones are signed online
+    async fn do_handle_request_transfer<R: ResponseHandler>(
         &self,
         request: &Request,
         response_handle: &mut R,
+        zone: &Zone,
     ) -> Result<ResponseInfo, Error> {
-        lazy_static! {
-            static ref TLD_ROOT: LowerName = LowerName::from_str("catmunch").unwrap();
-            static ref RDNS_IPV4: LowerName = LowerName::from_str("in-addr.arpa").unwrap();
-            static ref RDNS_IPV6: LowerName = LowerName::from_str("ip6.arpa").unwrap();
+        let src = request.src().ip();
+        if !self.config.transfer_acl.iter().any(|cidr| cidr.contains(&src)) {
+            warn!("Refused zone transfer of {} to {}", zone.name, src);
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::Refused)
+                .await;
+        }
+        // signatures and denials are made per response, so a secondary would serve the zone unsigned while the
+        // parent publishes its DS, failing validation
+        if self.signer.is_some() {
+            warn!("Refused zone transfer of {} to {}, the zone is signed online", zone.name, src);
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::Refused)
+                .await;
+        }
+        let snapshots = self.store.get_snapshots();
+        let current = match snapshots.last() {
+            Some(current) => current,
+            None => {
+                return self
+                    .do_handle_request_code(request, response_handle, ResponseCode::ServFail)
+                    .await
+            }
+        };
+        let soa = self.soa_record_at(&zone.name, current.serial);
+        let udp = matches!(request.protocol(), Protocol::Udp);
+        if request.query().query_type() == RecordType::IXFR {
+            let client_serial = request.name_servers().iter().find_map(|record| match record.data() {
+                Some(RData::SOA(soa)) => Some(soa.serial()),
+                _ => None,
+            });
+            // a single SOA tells the client it is up to date, or to retry over TCP
+            if udp || client_serial == Some(current.serial) {
+                return self.do_send_transfer(request, response_handle, vec![soa]).await;
+            }
+            if let Some(position) = snapshots.iter().position(|snapshot| Some(snapshot.serial) == client_serial) {
+                info!("Incremental zone transfer of {} from {} to {}", zone.name, snapshots[position].serial, src);
+                let mut records = vec![soa.clone()];
+                for versions in snapshots[position..].windows(2) {
+                    let (deleted, added) = transfer::diff(
+                        &self.zone_records(zone, &versions[0]),
+                        &self.zone_records(zone, &versions[1]),
+                    );
+                    records.push(self.soa_record_at(&zone.name, versions[0].serial));
+                    records.extend(deleted);
+                    records.push(self.soa_record_at(&zone.name, versions[1].serial));
+                    records.extend(added);
+                }
+                records.push(soa);
+                return self.do_send_transfer(request, response_handle, records).await;
+            }
+        } else if udp {
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::FormErr)
+                .await;
         }
+        info!("Full zone transfer of {} to {}", zone.name, src);
+        let mut records = vec![soa.clone()];
+        records.extend(self.zone_records(zone, current));
+        records.push(soa);
+        self.do_send_transfer(request, response_handle, records).await
+    }
+    async fn do_send_transfer<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+        records: Vec<Record>,
+    ) -> Result<ResponseInfo, Error> {
+        let mut header = Header::response_from_request(request.header());
+        header.set_authoritative(true);
+        let mut response_info = None;
+        for message in transfer::split_messages(&records) {
+            let builder = MessageResponseBuilder::from_message_request(request);
+            let response = builder.build(header, message.iter(), &[], &[], &[]);
+            response_info = Some(self.send_response(request, response_handle, response).await?);
+        }
+        Ok(response_info.unwrap())
+    }
+    async fn do_handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: &mut R,
+    ) -> Result<ResponseInfo, Error> {
         if request.op_code() != OpCode::Query {
             return self
                 .do_handle_request_code(request, response_handle, ResponseCode::ServFail)
@@ -290,19 +961,47 @@ impl Handler {
                 .do_handle_request_code(request, response_handle, ResponseCode::ServFail)
                 .await;
         }
-        match request.query().name() {
-            name if TLD_ROOT.zone_of(name) => {
-                self.do_handle_request_domain(request, response_handle)
+        if request.edns().is_some_and(|edns| edns.version() > 0) {
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::BADVERS)
+                .await;
+        }
+        if request_cookie(request).is_some_and(|cookie| !cookie_well_formed(cookie)) {
+            return self
+                .do_handle_request_code(request, response_handle, ResponseCode::FormErr)
+                .await;
+        }
+        if request.query().query_class() == DNSClass::CH {
+            return self.do_handle_request_chaos(request, response_handle).await;
+        }
+        let name = request.query().name();
+        if matches!(request.query().query_type(), RecordType::AXFR | RecordType::IXFR) {
+            return match self.zone_of(name) {
+                Some(zone) if zone.name == *name => {
+                    self.do_handle_request_transfer(request, response_handle, zone).await
+                }
+                _ => {
+                    self.do_handle_request_code(request, response_handle, ResponseCode::NotAuth)
+                        .await
+                }
+            };
+        }
+        match self.zone_of(name) {
+            Some(zone) if zone.name == *name => {
+                self.do_handle_request_apex(request, response_handle, &zone.name).await
+            }
+            Some(Zone { name: zone, kind: ZoneKind::Domain }) => {
+                self.do_handle_request_domain(request, response_handle, zone)
                     .await
             }
-            name if RDNS_IPV4.zone_of(name) => {
-                self.do_handle_request_ipv4(request, response_handle).await
+            Some(Zone { name: zone, kind: ZoneKind::Ipv4 }) => {
+                self.do_handle_request_ipv4(request, response_handle, zone).await
             }
-            name if RDNS_IPV6.zone_of(name) => {
-                self.do_handle_request_ipv6(request, response_handle).await
+            Some(Zone { name: zone, kind: ZoneKind::Ipv6 }) => {
+                self.do_handle_request_ipv6(request, response_handle, zone).await
             }
-            _ => {
-                self.do_handle_request_code(request, response_handle, ResponseCode::ServFail)
+            None => {
+                self.do_handle_request_code(request, response_handle, ResponseCode::Refused)
                     .await
             }
         }
@@ -0,0 +1,89 @@
+use siphasher::sip::SipHasher24;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length of a client cookie, and the accepted lengths of a whole COOKIE option (RFC 7873 4)
+pub const CLIENT_COOKIE_LENGTH: usize = 8;
+const COOKIE_MIN_LENGTH: usize = 16;
+const COOKIE_MAX_LENGTH: usize = 40;
+/// Server cookie layout of RFC 9018: version, reserved, timestamp and hash
+const SERVER_COOKIE_VERSION: u8 = 1;
+const SERVER_COOKIE_LENGTH: usize = 16;
+/// Server cookies are accepted for an hour after being issued, and 5 minutes before for clock skew (RFC 9018 4.3)
+const SERVER_COOKIE_LIFETIME: u32 = 3600;
+const SERVER_COOKIE_SKEW: u32 = 300;
+
+/// Issues and verifies interoperable server cookies (RFC 9018), keyed by a secret shared by all nodes
+pub struct CookieSigner {
+    secret: [u8; 16],
+}
+
+/// Whether a COOKIE option has a legal length, other lengths are answered with FORMERR
+pub fn cookie_well_formed(cookie: &[u8]) -> bool {
+    cookie.len() == CLIENT_COOKIE_LENGTH || (COOKIE_MIN_LENGTH..=COOKIE_MAX_LENGTH).contains(&cookie.len())
+}
+
+impl CookieSigner {
+    /// Uses a hex encoded 128 bits secret, or a random one when none is configured
+    pub fn new(secret: Option<&str>) -> Self {
+        let secret = match secret {
+            Some(secret) => data_encoding::HEXLOWER_PERMISSIVE
+                .decode(secret.as_bytes())
+                .ok()
+                .and_then(|secret| secret.try_into().ok())
+                .unwrap_or_else(|| panic!("DNS cookie secret must be 32 hex digits")),
+            None => {
+                // the hasher keys of RandomState are seeded by the OS random source
+                let mut secret = [0u8; 16];
+                secret[..8].copy_from_slice(&RandomState::new().build_hasher().finish().to_le_bytes());
+                secret[8..].copy_from_slice(&RandomState::new().build_hasher().finish().to_le_bytes());
+                secret
+            }
+        };
+        Self { secret }
+    }
+    /// The COOKIE option answering `cookie`: its client cookie and a server cookie, the one presented
+    /// being kept for the first half of its lifetime (RFC 9018 4.3)
+    pub fn response_cookie(&self, cookie: &[u8], client: IpAddr) -> Vec<u8> {
+        if self.age(cookie, client).is_some_and(|age| age < SERVER_COOKIE_LIFETIME as i32 / 2) {
+            return cookie.to_vec();
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        self.cookie_at(&cookie[..CLIENT_COOKIE_LENGTH], client, now)
+    }
+    /// Whether `cookie` carries a server cookie issued by us to `client` that has not expired
+    pub fn verify(&self, cookie: &[u8], client: IpAddr) -> bool {
+        self.age(cookie, client).is_some()
+    }
+    /// Age of the server cookie in `cookie` when it was issued by us to `client` and has not expired
+    fn age(&self, cookie: &[u8], client: IpAddr) -> Option<i32> {
+        if cookie.len() != CLIENT_COOKIE_LENGTH + SERVER_COOKIE_LENGTH || cookie[CLIENT_COOKIE_LENGTH] != SERVER_COOKIE_VERSION {
+            return None;
+        }
+        let timestamp = u32::from_be_bytes(cookie[12..16].try_into().unwrap());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        // serial number arithmetic, timestamps wrap around in 2106
+        let age = now.wrapping_sub(timestamp) as i32;
+        if age > SERVER_COOKIE_LIFETIME as i32 || age < -(SERVER_COOKIE_SKEW as i32) {
+            return None;
+        }
+        (self.cookie_at(&cookie[..CLIENT_COOKIE_LENGTH], client, timestamp) == cookie).then_some(age)
+    }
+    pub(super) fn cookie_at(&self, client_cookie: &[u8], client: IpAddr, timestamp: u32) -> Vec<u8> {
+        let mut cookie = client_cookie.to_vec();
+        cookie.extend([SERVER_COOKIE_VERSION, 0, 0, 0]);
+        cookie.extend(timestamp.to_be_bytes());
+        let mut input = cookie.clone();
+        match client {
+            IpAddr::V4(address) => input.extend(address.octets()),
+            IpAddr::V6(address) => input.extend(address.octets()),
+        }
+        // SipHash-2-4 is the MAC required by RFC 9018 for cookies to be shared between implementations
+        let mut hasher = SipHasher24::new_with_key(&self.secret);
+        hasher.write(&input);
+        cookie.extend(hasher.finish().to_le_bytes());
+        cookie
+    }
+}
@@ -0,0 +1,177 @@
+use hickory_server::proto::rr::dnssec::rdata::{DNSSECRData, DNSKEY, DS, NSEC, RRSIG};
+use hickory_server::proto::rr::dnssec::tbs::rrset_tbs;
+use hickory_server::proto::rr::dnssec::{Algorithm, DigestType, KeyFormat, KeyPair, Private};
+use hickory_server::proto::rr::{DNSClass, Name, RData, Record, RecordType};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Backdating of signature inceptions, for resolvers whose clock is behind ours
+const INCEPTION_OFFSET: u32 = 3600;
+
+/// Online signer of the served zones, using a single combined signing key for every zone
+pub struct ZoneSigner {
+    key: KeyPair<Private>,
+    algorithm: Algorithm,
+    dnskey: DNSKEY,
+    key_tag: u16,
+    validity: u32,
+}
+
+impl ZoneSigner {
+    /// Loads a PKCS#8 private key, PEM or DER encoded
+    pub fn load(path: &str, algorithm: u8, validity: u32) -> Self {
+        let bytes = fs::read(path).unwrap_or_else(|e| panic!("Failed to read DNSSEC key {}: {}", path, e));
+        let der = match std::str::from_utf8(&bytes) {
+            Ok(pem) if pem.contains("-----BEGIN") => pem_to_der(pem),
+            _ => bytes,
+        };
+        let algorithm = Algorithm::from_u8(algorithm);
+        let key = KeyFormat::Pkcs8
+            .decode_key(&der, None, algorithm)
+            .unwrap_or_else(|e| panic!("Failed to load DNSSEC key {}: {}", path, e));
+        let dnskey = key.to_dnskey(algorithm).unwrap();
+        let key_tag = dnskey.calculate_key_tag().unwrap();
+        Self {
+            key,
+            algorithm,
+            dnskey,
+            key_tag,
+            validity,
+        }
+    }
+    pub fn dnskey_record(&self, zone: &Name, ttl: u32) -> Record {
+        Record::from_rdata(zone.clone(), ttl, RData::DNSSEC(DNSSECRData::DNSKEY(self.dnskey.clone())))
+    }
+    /// DS of the key at `zone`, to be published in the parent or used as a trust anchor
+    pub fn ds_record(&self, zone: &Name, ttl: u32) -> Record {
+        let digest = self.dnskey.to_digest(zone, DigestType::SHA256).unwrap();
+        Record::from_rdata(
+            zone.clone(),
+            ttl,
+            RData::DNSSEC(DNSSECRData::DS(DS::new(
+                self.key_tag,
+                self.algorithm,
+                DigestType::SHA256,
+                digest.as_ref().to_vec(),
+            ))),
+        )
+    }
+    /// Returns one RRSIG per RRset in `records`
+    pub fn sign(&self, signer_name: &Name, records: &[Record]) -> Vec<Record> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        let inception = now.saturating_sub(INCEPTION_OFFSET);
+        let expiration = now.saturating_add(self.validity);
+        let mut rrsets: Vec<&Record> = vec![];
+        for record in records {
+            if !rrsets
+                .iter()
+                .any(|first| first.name() == record.name() && first.record_type() == record.record_type())
+            {
+                rrsets.push(record);
+            }
+        }
+        rrsets
+            .into_iter()
+            .map(|first| {
+                let name = first.name();
+                let tbs = rrset_tbs(
+                    name,
+                    DNSClass::IN,
+                    name.num_labels(),
+                    first.record_type(),
+                    self.algorithm,
+                    first.ttl(),
+                    expiration,
+                    inception,
+                    self.key_tag,
+                    signer_name,
+                    records,
+                )
+                .unwrap();
+                let signature = self.key.sign(self.algorithm, &tbs).unwrap();
+                Record::from_rdata(
+                    name.clone(),
+                    first.ttl(),
+                    RData::DNSSEC(DNSSECRData::RRSIG(RRSIG::new(
+                        first.record_type(),
+                        self.algorithm,
+                        name.num_labels(),
+                        first.ttl(),
+                        expiration,
+                        inception,
+                        self.key_tag,
+                        signer_name.clone(),
+                        signature,
+                    ))),
+                )
+            })
+            .collect()
+    }
+}
+
+fn pem_to_der(pem: &str) -> Vec<u8> {
+    let base64: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(|line| line.trim())
+        .collect();
+    data_encoding::BASE64
+        .decode(base64.as_bytes())
+        .unwrap_or_else(|e| panic!("Invalid PEM DNSSEC key: {}", e))
+}
+
+fn with_first_label(name: &Name, first: Vec<u8>) -> Name {
+    let mut labels: Vec<Vec<u8>> = name.iter().map(|label| label.to_vec()).collect();
+    labels[0] = first;
+    Name::from_labels(labels).unwrap()
+}
+
+/// NSEC at an existing `name`, proving that no types other than `types` exist there
+pub fn nsec_at(name: &Name, mut types: Vec<RecordType>, ttl: u32) -> Record {
+    types.extend([RecordType::RRSIG, RecordType::NSEC]);
+    // "\000.name" is the immediate successor of name in canonical order (RFC 4034 6.1)
+    let mut labels: Vec<Vec<u8>> = vec![vec![0]];
+    labels.extend(name.iter().map(|label| label.to_vec()));
+    let next = Name::from_labels(labels).unwrap();
+    Record::from_rdata(name.clone(), ttl, RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(next, types))))
+}
+
+/// Minimally covering NSEC proving that neither `name` nor any name below it exists (RFC 4470)
+pub fn nsec_covering(name: &Name, ttl: u32) -> Record {
+    let label = name.iter().next().unwrap();
+    // the owner sorts right before the name
+    let mut before = label.to_vec();
+    match before.pop() {
+        Some(0) | None => {}
+        Some(last) => {
+            // names compare case-insensitively, so stay below the uppercase letters
+            before.push(if (last - 1).is_ascii_uppercase() { b'@' } else { last - 1 });
+            if before.len() < 63 {
+                before.push(0xff);
+            }
+        }
+    }
+    let owner = if before.is_empty() {
+        name.base_name()
+    } else {
+        with_first_label(name, before)
+    };
+    // the next name sorts right after the name and all of its descendants
+    let mut after = label.to_vec();
+    if after.len() < 63 {
+        after.push(0);
+    } else if let Some(last) = after.last_mut() {
+        *last = last.saturating_add(1);
+    }
+    let next = with_first_label(name, after);
+    Record::from_rdata(
+        owner,
+        ttl,
+        RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(next, vec![RecordType::RRSIG, RecordType::NSEC]))),
+    )
+}
+
+/// The wildcard name directly below `name`
+pub fn wildcard_of(name: &Name) -> Name {
+    Name::from_labels([b"*".as_slice()]).unwrap().append_domain(name).unwrap()
+}
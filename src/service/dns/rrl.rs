@@ -0,0 +1,124 @@
+use crate::config::Config;
+use cidr::{IpCidr, IpInet};
+use hickory_server::proto::op::{Header, ResponseCode};
+use log::info;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Number of tracked client prefixes above which the idle ones are forgotten, at most once per window
+const RRL_TABLE_SIZE: usize = 100000;
+
+/// Kinds of responses accounted separately, so that a flood of one kind does not starve the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResponseKind {
+    Answer,
+    Referral,
+    NxDomain,
+    Error,
+}
+
+impl ResponseKind {
+    /// Classifies a response from its header, our only non-authoritative successful responses being referrals
+    pub fn of(header: &Header) -> Self {
+        match header.response_code() {
+            ResponseCode::NoError if header.authoritative() => ResponseKind::Answer,
+            ResponseCode::NoError => ResponseKind::Referral,
+            ResponseCode::NXDomain => ResponseKind::NxDomain,
+            _ => ResponseKind::Error,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Send,
+    Drop,
+    /// Send an empty truncated response, so that legitimate clients retry over TCP
+    Slip,
+}
+
+struct Bucket {
+    balance: f64,
+    updated: Instant,
+    limited: u32,
+}
+
+/// Response rate limiting of UDP clients, per client prefix and response kind
+pub struct RateLimiter {
+    rate: f64,
+    window: f64,
+    slip: u32,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    table: HashMap<(IpCidr, ResponseKind), Bucket>,
+    swept: Instant,
+}
+
+impl RateLimiter {
+    /// Returns `None` when rate limiting is disabled
+    pub fn new(config: &Config) -> Option<Self> {
+        if config.rrl_responses_per_second == 0 {
+            return None;
+        }
+        Some(Self {
+            rate: config.rrl_responses_per_second as f64,
+            window: config.rrl_window as f64,
+            slip: config.rrl_slip,
+            ipv4_prefix: config.rrl_ipv4_prefix,
+            ipv6_prefix: config.rrl_ipv6_prefix,
+            buckets: Mutex::new(Buckets {
+                table: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        })
+    }
+    /// Accounts a response of `kind` to `client`, the credit of a prefix being refilled at the configured rate
+    /// up to one second worth of responses, and its debt being capped to the window
+    pub fn check(&self, client: IpAddr, kind: ResponseKind) -> Action {
+        self.check_at(client, kind, Instant::now())
+    }
+    pub(super) fn check_at(&self, client: IpAddr, kind: ResponseKind, now: Instant) -> Action {
+        let prefix_length = match client {
+            IpAddr::V4(_) => self.ipv4_prefix,
+            IpAddr::V6(_) => self.ipv6_prefix,
+        };
+        let prefix = IpInet::new(client, prefix_length).unwrap().network();
+        let mut buckets = self.buckets.lock().unwrap();
+        let window = self.window;
+        if buckets.table.len() >= RRL_TABLE_SIZE && now.duration_since(buckets.swept).as_secs_f64() >= window {
+            buckets.table.retain(|_, bucket| now.duration_since(bucket.updated).as_secs_f64() < window);
+            buckets.swept = now;
+        }
+        let bucket = buckets.table.entry((prefix, kind)).or_insert(Bucket {
+            balance: self.rate,
+            updated: now,
+            limited: 0,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.balance = (bucket.balance + elapsed * self.rate).min(self.rate) - 1.0;
+        bucket.balance = bucket.balance.max(-self.rate * self.window);
+        bucket.updated = now;
+        if bucket.balance >= 0.0 {
+            if bucket.limited > 0 {
+                info!("Stopped limiting {:?} responses to {} after {} responses", kind, prefix, bucket.limited);
+                bucket.limited = 0;
+            }
+            return Action::Send;
+        }
+        bucket.limited += 1;
+        if bucket.limited == 1 {
+            info!("Limiting {:?} responses to {}", kind, prefix);
+        }
+        if self.slip > 0 && bucket.limited.is_multiple_of(self.slip) {
+            Action::Slip
+        } else {
+            Action::Drop
+        }
+    }
+}
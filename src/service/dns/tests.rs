@@ -0,0 +1,788 @@
+use super::*;
+use crate::resource::contacts::Contacts;
+use crate::resource::domain::{Domain, Records, Srv};
+use crate::resource::inet6num::Inet6num;
+use crate::resource::inetnum::Inetnum;
+use crate::resource::Resource;
+use crate::store::memory::MemoryStore;
+use crate::store::Change;
+use crate::util::cidr::{Ipv4CidrWrapper, Ipv6CidrWrapper};
+use clap::Parser;
+use hickory_server::authority::{MessageRequest, MessageResponse};
+use hickory_server::proto::op::Query;
+use hickory_server::proto::rr::dnssec::rdata::{DNSSECRData, DNSKEY, NSEC};
+use hickory_server::proto::rr::dnssec::{Algorithm, KeyPair, Private, Verifier};
+use hickory_server::proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use hickory_server::proto::serialize::binary::{BinDecodable, BinEncodable, BinEncoder};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Captures the responses of the handler instead of sending them
+#[derive(Clone, Default)]
+struct CapturingHandle {
+    responses: Arc<Mutex<Vec<Message>>>,
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for CapturingHandle {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut bytes = vec![];
+        let info = response.destructive_emit(&mut BinEncoder::new(&mut bytes))?;
+        self.responses.lock().unwrap().push(Message::from_vec(&bytes)?);
+        Ok(info)
+    }
+}
+
+fn ns(server: &str, a: Option<&str>) -> NS {
+    NS {
+        server: server.to_string(),
+        a: a.map(|a| Ipv4Addr::from_str(a).unwrap()),
+        aaaa: None,
+    }
+}
+
+fn handler() -> Handler {
+    handler_with(&[])
+}
+
+fn handler_with(args: &[&str]) -> Handler {
+    let mut config_args = vec!["dns-whois-server", "--git-repo", "registry", "--apex-ns", "ns.catmunch."];
+    config_args.extend(args);
+    let config: &'static Config = Box::leak(Box::new(Config::parse_from(config_args)));
+    let mut store = MemoryStore::new();
+    store.set(&Vec::from([
+        Resource::Domain(Domain {
+            domain: "meow.catmunch".to_string(),
+            description: None,
+            ns: vec![ns("ns1.meow.catmunch", Some("10.1.0.53")), ns("ns.purr.catmunch", Some("10.1.0.54"))],
+            ds: Some(vec![crate::resource::domain::DS {
+                key_tag: 12345,
+                algorithm: 13,
+                digest_type: 2,
+                digest: "e2d3c916f6deeac73294e8268fb5885044a833fc5459588f4a9184cfc41a5766".to_string(),
+            }]),
+            records: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "purr.catmunch".to_string(),
+            description: None,
+            ns: vec![],
+            ds: None,
+            records: Some(Records {
+                a: Some(vec![Ipv4Addr::new(10, 1, 0, 80)]),
+                txt: Some(vec!["v=spf1 -all".to_string()]),
+                srv: Some(vec![Srv {
+                    name: "_sip._tcp".to_string(),
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    target: "purr.catmunch.".to_string(),
+                }]),
+                ..Default::default()
+            }),
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "www.purr.catmunch".to_string(),
+            description: None,
+            ns: vec![],
+            ds: None,
+            records: Some(Records {
+                cname: Some("purr.catmunch.".to_string()),
+                ..Default::default()
+            }),
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "loud.catmunch".to_string(),
+            description: None,
+            ns: vec![],
+            ds: None,
+            records: Some(Records {
+                a: Some((1..=40).map(|i| Ipv4Addr::new(10, 1, 1, i)).collect()),
+                ..Default::default()
+            }),
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "lab.hiss.catmunch".to_string(),
+            description: None,
+            ns: vec![ns("ns.lab.hiss.catmunch", Some("10.1.0.55"))],
+            ds: None,
+            records: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.1.0.0/16").unwrap()),
+            description: None,
+            ns: Some(vec![ns("ns1.meow.catmunch", None)]),
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.2.0.0/16").unwrap()),
+            description: None,
+            ns: None,
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.2.3.64/26").unwrap()),
+            description: None,
+            ns: Some(vec![ns("ns1.meow.catmunch", None)]),
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.3.4.0/24").unwrap()),
+            description: None,
+            ns: Some(vec![ns("ns1.meow.catmunch", None)]),
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.4.0.0/24").unwrap()),
+            description: None,
+            ns: None,
+            ptr: Some(BTreeMap::from([(Ipv4Addr::new(10, 4, 0, 1), "gw.purr.catmunch".to_string())])),
+            ptr_template: Some("{ip-dashed}.pool.purr.catmunch".to_string()),
+            contacts: Contacts::default(),
+        }),
+        Resource::Inet6num(Inet6num {
+            cidr: Ipv6CidrWrapper(Ipv6Cidr::from_str("fc75:2::/32").unwrap()),
+            description: None,
+            ns: None,
+            ptr: Some(BTreeMap::from([(Ipv6Addr::from_str("fc75:2::1").unwrap(), "gw.purr.catmunch".to_string())])),
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Inet6num(Inet6num {
+            cidr: Ipv6CidrWrapper(Ipv6Cidr::from_str("fc75:1::/32").unwrap()),
+            description: None,
+            ns: Some(vec![ns("ns1.meow.catmunch", None)]),
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+    ]), 1);
+    Handler::new(config, Box::new(store))
+}
+
+async fn query(handler: &Handler, name: &str, query_type: RecordType) -> Message {
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str(name).unwrap(), query_type));
+    send(handler, message).await
+}
+
+async fn query_edns(handler: &Handler, name: &str, query_class: DNSClass, edns: Edns) -> Message {
+    let mut query = Query::query(Name::from_str(name).unwrap(), RecordType::TXT);
+    query.set_query_class(query_class);
+    let mut message = Message::new();
+    message.add_query(query);
+    message.set_edns(edns);
+    send(handler, message).await
+}
+
+async fn send(handler: &Handler, message: Message) -> Message {
+    let mut responses = send_all(handler, message).await;
+    assert_eq!(responses.len(), 1);
+    responses.pop().unwrap()
+}
+
+/// The responses sent, none when the response is dropped
+async fn send_all(handler: &Handler, message: Message) -> Vec<Message> {
+    send_over(handler, message, Protocol::Udp).await
+}
+
+async fn send_over(handler: &Handler, mut message: Message, protocol: Protocol) -> Vec<Message> {
+    message.set_id(1);
+    let message = MessageRequest::from_bytes(&message.to_vec().unwrap()).unwrap();
+    let src = SocketAddr::from_str("192.0.2.1:53000").unwrap();
+    let request = Request::new(message, src, protocol);
+    let response_handle = CapturingHandle::default();
+    handler.handle_request(&request, response_handle.clone()).await;
+    let responses = response_handle.responses.lock().unwrap();
+    responses.clone()
+}
+
+/// Path of a signing key generated once for the tests
+fn dnssec_key() -> &'static str {
+    static KEY: OnceLock<String> = OnceLock::new();
+    KEY.get_or_init(|| {
+        let der = KeyPair::<Private>::generate_pkcs8(Algorithm::ECDSAP256SHA256).unwrap();
+        let path = std::env::temp_dir().join(format!("dnssec-{}.der", std::process::id()));
+        std::fs::write(&path, der).unwrap();
+        path.to_str().unwrap().to_string()
+    })
+}
+
+async fn query_dnssec(handler: &Handler, name: &str, query_type: RecordType) -> Message {
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str(name).unwrap(), query_type));
+    let mut edns = Edns::new();
+    edns.set_max_payload(4096);
+    edns.set_dnssec_ok(true);
+    message.set_edns(edns);
+    send(handler, message).await
+}
+
+/// Checks the RRSIG of every RRset of `records` against `dnskey`, returning the types covered
+fn verify_signatures(dnskey: &DNSKEY, records: &[Record]) -> Vec<RecordType> {
+    records
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::DNSSEC(DNSSECRData::RRSIG(rrsig))) => Some((record.name(), rrsig)),
+            _ => None,
+        })
+        .map(|(name, rrsig)| {
+            let rrset: Vec<Record> = records
+                .iter()
+                .filter(|record| record.name() == name && record.record_type() == rrsig.type_covered())
+                .cloned()
+                .collect();
+            dnskey.verify_rrsig(name, DNSClass::IN, rrsig, &rrset).unwrap();
+            rrsig.type_covered()
+        })
+        .collect()
+}
+
+fn nsec_of(record: &Record) -> &NSEC {
+    match record.data() {
+        Some(RData::DNSSEC(DNSSECRData::NSEC(nsec))) => nsec,
+        _ => panic!("{} is not an NSEC", record),
+    }
+}
+
+fn types(records: &[Record]) -> Vec<RecordType> {
+    records.iter().map(|record| record.record_type()).collect()
+}
+
+#[tokio::test]
+async fn test_apex_soa() {
+    let response = query(&handler(), "catmunch.", RecordType::SOA).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::SOA]);
+}
+#[tokio::test]
+async fn test_udp_truncation() {
+    let handler = handler();
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str("loud.catmunch.").unwrap(), RecordType::A));
+    let response = send(&handler, message.clone()).await;
+    assert!(response.truncated());
+    assert!(response.answers().is_empty());
+    let response = send_over(&handler, message.clone(), Protocol::Tcp).await.pop().unwrap();
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), 40);
+    // a larger payload advertised over EDNS fits the whole answer
+    let mut edns = Edns::new();
+    edns.set_max_payload(4096);
+    message.set_edns(edns);
+    let response = send(&handler, message).await;
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), 40);
+}
+#[tokio::test]
+async fn test_tcp_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = ServerFuture::new(handler());
+    server.register_listener(listener, Duration::from_secs(1));
+    let mut message = Message::new();
+    message.set_id(7);
+    message.add_query(Query::query(Name::from_str("loud.catmunch.").unwrap(), RecordType::A));
+    let bytes = message.to_vec().unwrap();
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&(bytes.len() as u16).to_be_bytes()).await.unwrap();
+    stream.write_all(&bytes).await.unwrap();
+    let mut length = [0; 2];
+    stream.read_exact(&mut length).await.unwrap();
+    let mut bytes = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut bytes).await.unwrap();
+    let response = Message::from_vec(&bytes).unwrap();
+    assert_eq!(response.id(), 7);
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), 40);
+    server.shutdown_gracefully().await.unwrap();
+}
+#[tokio::test]
+async fn test_referral_at_cut() {
+    let response = query(&handler(), "meow.catmunch.", RecordType::NS).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.authoritative());
+    assert!(response.answers().is_empty());
+    assert_eq!(types(response.name_servers()), vec![RecordType::NS, RecordType::NS]);
+    assert_eq!(types(response.additionals()), vec![RecordType::A, RecordType::A]);
+}
+#[tokio::test]
+async fn test_referral_below_cut() {
+    let response = query(&handler(), "www.meow.catmunch.", RecordType::A).await;
+    assert!(!response.authoritative());
+    assert!(response.answers().is_empty());
+    assert!(response
+        .name_servers()
+        .iter()
+        .all(|record| record.name() == &Name::from_str("meow.catmunch.").unwrap()));
+}
+#[tokio::test]
+async fn test_ds_at_cut() {
+    let handler = handler();
+    let response = query(&handler, "meow.catmunch.", RecordType::DS).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::DS]);
+    let response = query(&handler, "lab.hiss.catmunch.", RecordType::DS).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert!(response.answers().is_empty());
+    assert_eq!(types(response.name_servers()), vec![RecordType::SOA]);
+}
+#[tokio::test]
+async fn test_ds_below_cut() {
+    let response = query(&handler(), "www.meow.catmunch.", RecordType::DS).await;
+    assert!(!response.authoritative());
+    assert!(response.answers().is_empty());
+}
+#[tokio::test]
+async fn test_glue_answer() {
+    let handler = handler();
+    let response = query(&handler, "ns1.meow.catmunch.", RecordType::A).await;
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::A]);
+    let response = query(&handler, "ns1.meow.catmunch.", RecordType::AAAA).await;
+    assert!(!response.authoritative());
+    assert!(response.answers().is_empty());
+}
+#[tokio::test]
+async fn test_nxdomain() {
+    let response = query(&handler(), "woof.catmunch.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert!(response.authoritative());
+    assert_eq!(types(response.name_servers()), vec![RecordType::SOA]);
+}
+#[tokio::test]
+async fn test_empty_non_terminal() {
+    let handler = handler();
+    let response = query(&handler, "hiss.catmunch.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert!(response.answers().is_empty());
+    assert_eq!(types(response.name_servers()), vec![RecordType::SOA]);
+    let response = query(&handler, "3.10.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    let response = query(&handler, "5.7.c.f.ip6.arpa.", RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    let response = query(&handler, "5.10.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+}
+#[tokio::test]
+async fn test_reverse() {
+    let handler = handler();
+    let response = query(&handler, "4.3.1.10.in-addr.arpa.", RecordType::PTR).await;
+    assert!(!response.authoritative());
+    assert!(response
+        .name_servers()
+        .iter()
+        .all(|record| record.name() == &Name::from_str("1.10.in-addr.arpa.").unwrap()));
+    // registered without delegation
+    let response = query(&handler, "4.3.2.10.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert!(response.answers().is_empty());
+    let response = query(&handler, "1.0.0.0.1.0.0.0.5.7.c.f.ip6.arpa.", RecordType::PTR).await;
+    assert!(!response.authoritative());
+    assert_eq!(types(response.name_servers()), vec![RecordType::NS]);
+}
+#[tokio::test]
+async fn test_reverse_classless() {
+    let handler = handler();
+    let response = query(&handler, "65.3.2.10.in-addr.arpa.", RecordType::PTR).await;
+    assert!(response.authoritative());
+    assert_eq!(
+        response.answers()[0].data(),
+        Some(&RData::CNAME(rdata::CNAME(Name::from_str("65.64-127.3.2.10.in-addr.arpa.").unwrap())))
+    );
+    let response = query(&handler, "65.64-127.3.2.10.in-addr.arpa.", RecordType::PTR).await;
+    assert!(!response.authoritative());
+    assert!(response
+        .name_servers()
+        .iter()
+        .all(|record| record.name() == &Name::from_str("64-127.3.2.10.in-addr.arpa.").unwrap()));
+    let response = query(&handler, "65.0-127.3.2.10.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+}
+#[tokio::test]
+async fn test_out_of_zone() {
+    let response = query(&handler(), "example.com.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+}
+#[tokio::test]
+async fn test_inline_records() {
+    let handler = handler();
+    let response = query(&handler, "purr.catmunch.", RecordType::A).await;
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::A]);
+    let response = query(&handler, "purr.catmunch.", RecordType::TXT).await;
+    assert_eq!(types(response.answers()), vec![RecordType::TXT]);
+    let response = query(&handler, "_sip._tcp.purr.catmunch.", RecordType::SRV).await;
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::SRV]);
+    let response = query(&handler, "www.purr.catmunch.", RecordType::A).await;
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::CNAME]);
+}
+#[tokio::test]
+async fn test_inline_negative() {
+    let handler = handler();
+    let response = query(&handler, "purr.catmunch.", RecordType::AAAA).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert!(response.answers().is_empty());
+    assert_eq!(types(response.name_servers()), vec![RecordType::SOA]);
+    let response = query(&handler, "_tcp.purr.catmunch.", RecordType::SRV).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.answers().is_empty());
+    let response = query(&handler, "mail.purr.catmunch.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+}
+#[tokio::test]
+async fn test_reverse_ptr() {
+    let handler = handler();
+    let ptr = |name: &str| Some(RData::PTR(rdata::PTR(Name::from_str(name).unwrap())));
+    let response = query(&handler, "1.0.4.10.in-addr.arpa.", RecordType::PTR).await;
+    assert!(response.authoritative());
+    assert_eq!(response.answers()[0].data(), ptr("gw.purr.catmunch.").as_ref());
+    let response = query(&handler, "2.0.4.10.in-addr.arpa.", RecordType::PTR).await;
+    assert_eq!(response.answers()[0].data(), ptr("10-4-0-2.pool.purr.catmunch.").as_ref());
+    let response = query(&handler, "2.0.4.10.in-addr.arpa.", RecordType::TXT).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.answers().is_empty());
+    let name = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.2.0.0.0.5.7.c.f.ip6.arpa.";
+    let response = query(&handler, name, RecordType::PTR).await;
+    assert_eq!(response.answers()[0].data(), ptr("gw.purr.catmunch.").as_ref());
+    let name = "2.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.2.0.0.0.5.7.c.f.ip6.arpa.";
+    let response = query(&handler, name, RecordType::PTR).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.answers().is_empty());
+}
+#[tokio::test]
+async fn test_edns() {
+    let handler = handler();
+    let mut edns = Edns::new();
+    edns.set_max_payload(4096);
+    edns.options_mut().insert(EdnsOption::Unknown(EdnsCode::NSID.into(), vec![]));
+    let response = query_edns(&handler, "catmunch.", DNSClass::IN, edns).await;
+    let edns = response.extensions().as_ref().unwrap();
+    assert_eq!(edns.max_payload(), 1232);
+    assert_eq!(
+        edns.option(EdnsCode::NSID),
+        Some(&EdnsOption::Unknown(EdnsCode::NSID.into(), b"Default Node".to_vec()))
+    );
+    let mut edns = Edns::new();
+    edns.set_version(1);
+    let response = query_edns(&handler, "catmunch.", DNSClass::IN, edns).await;
+    // BADVERS shares its code with BADSIG, which is what it decodes to
+    assert_eq!(u16::from(response.response_code()), u16::from(ResponseCode::BADVERS));
+}
+#[tokio::test]
+async fn test_cookies() {
+    let handler = handler();
+    let cookie_of = |response: &Message| match response.extensions().as_ref().unwrap().option(EdnsCode::Cookie) {
+        Some(EdnsOption::Unknown(_, cookie)) => cookie.clone(),
+        _ => panic!("no cookie"),
+    };
+    let with_cookie = |cookie: Vec<u8>| {
+        let mut edns = Edns::new();
+        edns.options_mut().insert(EdnsOption::Unknown(EdnsCode::Cookie.into(), cookie));
+        edns
+    };
+    let client_cookie = vec![0x24, 0x64, 0xc4, 0xab, 0xcf, 0x10, 0xc9, 0x57];
+    let response = query_edns(&handler, "catmunch.", DNSClass::IN, with_cookie(client_cookie.clone())).await;
+    let cookie = cookie_of(&response);
+    assert_eq!(cookie.len(), 24);
+    assert_eq!(cookie[..8], client_cookie);
+    // a fresh server cookie is kept
+    let response = query_edns(&handler, "catmunch.", DNSClass::IN, with_cookie(cookie.clone())).await;
+    assert_eq!(cookie_of(&response), cookie);
+    let response = query_edns(&handler, "catmunch.", DNSClass::IN, with_cookie(vec![1, 2, 3])).await;
+    assert_eq!(response.response_code(), ResponseCode::FormErr);
+}
+#[test]
+fn test_server_cookie() {
+    // RFC 9018 appendix A.1
+    let signer = CookieSigner::new(Some("e5e973e5a6b2a43f48e7dc849e37bfcf"));
+    let cookie = signer.cookie_at(
+        &[0x24, 0x64, 0xc4, 0xab, 0xcf, 0x10, 0xc9, 0x57],
+        "198.51.100.100".parse().unwrap(),
+        1559731985,
+    );
+    assert_eq!(data_encoding::HEXLOWER.encode(&cookie[8..]), "010000005cf79f111f8130c3eee29480");
+}
+#[tokio::test]
+async fn test_chaos() {
+    let handler = handler();
+    let response = query_edns(&handler, "id.server.", DNSClass::CH, Edns::new()).await;
+    assert!(response.authoritative());
+    assert_eq!(
+        response.answers()[0].data(),
+        Some(&RData::TXT(rdata::TXT::new(vec!["Default Node".to_string()])))
+    );
+    let response = query_edns(&handler, "version.bind.", DNSClass::CH, Edns::new()).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+}
+#[test]
+fn test_rate_limiter() {
+    let config = Config::parse_from(["dns-whois-server", "--git-repo", "registry", "--rrl-responses-per-second", "2"]);
+    let rate_limiter = RateLimiter::new(&config).unwrap();
+    let client: IpAddr = "192.0.2.1".parse().unwrap();
+    let now = Instant::now();
+    let actions: Vec<Action> = (0..6)
+        .map(|_| rate_limiter.check_at(client, ResponseKind::Referral, now))
+        .collect();
+    assert_eq!(
+        actions,
+        [Action::Send, Action::Send, Action::Drop, Action::Slip, Action::Drop, Action::Slip]
+    );
+    // accounted per prefix and kind
+    assert_eq!(rate_limiter.check_at("192.0.2.200".parse().unwrap(), ResponseKind::Referral, now), Action::Drop);
+    assert_eq!(rate_limiter.check_at("192.0.3.1".parse().unwrap(), ResponseKind::Referral, now), Action::Send);
+    assert_eq!(rate_limiter.check_at(client, ResponseKind::NxDomain, now), Action::Send);
+    // the debt is paid at the configured rate
+    let later = now + Duration::from_secs(5);
+    assert_eq!(rate_limiter.check_at(client, ResponseKind::Referral, later), Action::Send);
+}
+#[tokio::test]
+async fn test_rate_limited_handler() {
+    let handler = handler_with(&["--rrl-responses-per-second", "1"]);
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str("woof.catmunch.").unwrap(), RecordType::A));
+    let responses = send_all(&handler, message.clone()).await;
+    assert_eq!(responses[0].response_code(), ResponseCode::NXDomain);
+    assert!(send_all(&handler, message.clone()).await.is_empty());
+    let responses = send_all(&handler, message.clone()).await;
+    assert!(responses[0].truncated());
+    assert!(responses[0].name_servers().is_empty());
+    // clients proving their address with a cookie are not limited
+    let mut edns = Edns::new();
+    let cookie = handler.cookies.response_cookie(&[1; 8], "192.0.2.1".parse().unwrap());
+    edns.options_mut().insert(EdnsOption::Unknown(EdnsCode::Cookie.into(), cookie));
+    message.set_edns(edns);
+    let responses = send_all(&handler, message).await;
+    assert_eq!(responses[0].response_code(), ResponseCode::NXDomain);
+    assert!(!responses[0].truncated());
+}
+#[tokio::test]
+async fn test_dnssec_signing() {
+    let handler = handler_with(&["--dnssec-key", dnssec_key()]);
+    let response = query_dnssec(&handler, "catmunch.", RecordType::DNSKEY).await;
+    assert!(response.extensions().as_ref().unwrap().dnssec_ok());
+    assert_eq!(types(response.answers()), vec![RecordType::DNSKEY, RecordType::RRSIG]);
+    let dnskey = match response.answers()[0].data() {
+        Some(RData::DNSSEC(DNSSECRData::DNSKEY(dnskey))) => dnskey.clone(),
+        _ => panic!("no DNSKEY"),
+    };
+    assert_eq!(verify_signatures(&dnskey, response.answers()), vec![RecordType::DNSKEY]);
+    let response = query_dnssec(&handler, "purr.catmunch.", RecordType::A).await;
+    assert_eq!(verify_signatures(&dnskey, response.answers()), vec![RecordType::A]);
+    // the signatures are only sent to clients asking for them
+    let response = query(&handler, "purr.catmunch.", RecordType::A).await;
+    assert_eq!(types(response.answers()), vec![RecordType::A]);
+}
+#[tokio::test]
+async fn test_dnssec_delegation() {
+    let handler = handler_with(&["--dnssec-key", dnssec_key()]);
+    let dnskey = query_dnssec(&handler, "catmunch.", RecordType::DNSKEY).await.answers()[0].clone();
+    let dnskey = match dnskey.data() {
+        Some(RData::DNSSEC(DNSSECRData::DNSKEY(dnskey))) => dnskey.clone(),
+        _ => panic!("no DNSKEY"),
+    };
+    // a secure delegation carries its signed DS, the NS of the child zone are not signed
+    let response = query_dnssec(&handler, "www.meow.catmunch.", RecordType::A).await;
+    assert_eq!(
+        types(response.name_servers()),
+        vec![RecordType::NS, RecordType::NS, RecordType::DS, RecordType::RRSIG]
+    );
+    assert_eq!(verify_signatures(&dnskey, response.name_servers()), vec![RecordType::DS]);
+    let response = query_dnssec(&handler, "meow.catmunch.", RecordType::DS).await;
+    assert!(response.authoritative());
+    assert_eq!(types(response.answers()), vec![RecordType::DS, RecordType::RRSIG]);
+    // an insecure delegation proves that it has no DS
+    let response = query_dnssec(&handler, "lab.hiss.catmunch.", RecordType::A).await;
+    let nsec = response.name_servers().iter().find(|record| record.record_type() == RecordType::NSEC).unwrap();
+    assert!(nsec_of(nsec).type_bit_maps().contains(&RecordType::NS));
+    assert!(!nsec_of(nsec).type_bit_maps().contains(&RecordType::DS));
+    assert_eq!(verify_signatures(&dnskey, response.name_servers()), vec![RecordType::NSEC]);
+}
+#[tokio::test]
+async fn test_dnssec_denial() {
+    let handler = handler_with(&["--dnssec-key", dnssec_key()]);
+    let response = query_dnssec(&handler, "woof.catmunch.", RecordType::A).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    let nsecs: Vec<&Record> = response
+        .name_servers()
+        .iter()
+        .filter(|record| record.record_type() == RecordType::NSEC)
+        .collect();
+    assert_eq!(nsecs.len(), 2);
+    // the name and the wildcard of its parent are covered
+    for (nsec, name) in nsecs.iter().zip(["woof.catmunch.", "*.catmunch."]) {
+        let name = Name::from_str(name).unwrap();
+        assert!(nsec.name() < &name && &name < nsec_of(nsec).next_domain_name());
+    }
+    assert_eq!(
+        types(response.name_servers()).iter().filter(|t| **t == RecordType::RRSIG).count(),
+        3
+    );
+    // no data at an existing name lists the types it has
+    let response = query_dnssec(&handler, "purr.catmunch.", RecordType::AAAA).await;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    let nsec = response.name_servers().iter().find(|record| record.record_type() == RecordType::NSEC).unwrap();
+    assert_eq!(nsec.name(), &Name::from_str("purr.catmunch.").unwrap());
+    assert!(nsec_of(nsec).type_bit_maps().contains(&RecordType::A));
+    assert!(!nsec_of(nsec).type_bit_maps().contains(&RecordType::AAAA));
+}
+fn transfer_query(name: &str, query_type: RecordType, client_serial: Option<u32>) -> Message {
+    let mut message = Message::new();
+    message.add_query(Query::query(Name::from_str(name).unwrap(), query_type));
+    if let Some(serial) = client_serial {
+        let soa = rdata::SOA::new(Name::root(), Name::root(), serial, 0, 0, 0, 0);
+        message.add_name_server(Record::from_rdata(Name::from_str(name).unwrap(), 0, RData::SOA(soa)));
+    }
+    message
+}
+fn soa_serial(record: &Record) -> u32 {
+    match record.data() {
+        Some(RData::SOA(soa)) => soa.serial(),
+        _ => panic!("{} is not an SOA", record),
+    }
+}
+#[tokio::test]
+async fn test_transfer_acl() {
+    let message = transfer_query("catmunch.", RecordType::AXFR, None);
+    let responses = send_over(&handler(), message.clone(), Protocol::Tcp).await;
+    assert_eq!(responses[0].response_code(), ResponseCode::Refused);
+    let handler = handler_with(&["--transfer-acl", "192.0.2.0/24"]);
+    let responses = send_over(&handler, message.clone(), Protocol::Tcp).await;
+    let records: Vec<Record> = responses.iter().flat_map(|response| response.answers().to_vec()).collect();
+    assert_eq!(records.first().unwrap().record_type(), RecordType::SOA);
+    assert_eq!(records.last().unwrap().record_type(), RecordType::SOA);
+    assert!(records.iter().any(|record| record.record_type() == RecordType::DS));
+    // AXFR is only served over TCP
+    assert_eq!(send(&handler, message).await.response_code(), ResponseCode::FormErr);
+    let response = send(&handler, transfer_query("meow.catmunch.", RecordType::AXFR, None)).await;
+    assert_eq!(response.response_code(), ResponseCode::NotAuth);
+}
+#[tokio::test]
+async fn test_transfer_signed_zone() {
+    // a secondary could not sign the zone, and would serve it unsigned below the DS of the parent
+    let handler = handler_with(&["--transfer-acl", "192.0.2.0/24", "--dnssec-key", dnssec_key()]);
+    let responses = send_over(&handler, transfer_query("catmunch.", RecordType::AXFR, None), Protocol::Tcp).await;
+    assert_eq!(responses[0].response_code(), ResponseCode::Refused);
+}
+#[tokio::test]
+async fn test_incremental_transfer() {
+    let handler = handler_with(&["--transfer-acl", "192.0.2.0/24"]);
+    let domain = Domain {
+        domain: "hiss.catmunch".to_string(),
+        description: None,
+        ns: vec![ns("ns.hiss.catmunch", Some("10.1.0.56"))],
+        ds: None,
+        records: None,
+        contacts: Contacts::default(),
+    };
+    let mut store = handler.store.clone_dyn();
+    store.apply(&[Change::Added(PathBuf::from("domain/hiss.catmunch"), Resource::Domain(domain))], 2);
+    let message = transfer_query("catmunch.", RecordType::IXFR, Some(1));
+    let records = send_over(&handler, message, Protocol::Tcp).await.pop().unwrap().answers().to_vec();
+    assert_eq!(
+        types(&records),
+        [RecordType::SOA, RecordType::SOA, RecordType::SOA, RecordType::NS, RecordType::A, RecordType::SOA]
+    );
+    let serials: Vec<u32> = records
+        .iter()
+        .filter(|record| record.record_type() == RecordType::SOA)
+        .map(soa_serial)
+        .collect();
+    assert_eq!(serials, [2, 1, 2, 2]);
+    // an up to date client gets the current SOA alone
+    let message = transfer_query("catmunch.", RecordType::IXFR, Some(2));
+    let records = send_over(&handler, message, Protocol::Tcp).await.pop().unwrap().answers().to_vec();
+    assert_eq!(types(&records), [RecordType::SOA]);
+}
+#[test]
+fn test_transfer_records() {
+    let zone = Zone {
+        name: LowerName::from_str("catmunch.").unwrap(),
+        kind: ZoneKind::Domain,
+    };
+    let delegated = |domain: &str| {
+        Resource::Domain(Domain {
+            domain: domain.to_string(),
+            description: None,
+            ns: vec![ns("ns.meow.catmunch", Some("10.1.0.53")), ns("ns.example.com", Some("192.0.2.53"))],
+            ds: None,
+            records: None,
+            contacts: Contacts::default(),
+        })
+    };
+    let records = transfer::resource_records(&zone, &[delegated("meow.catmunch"), delegated("purr.catmunch")]);
+    // shared glue is sent once, glue outside of the zone not at all
+    assert_eq!(types(&records), [RecordType::NS, RecordType::NS, RecordType::NS, RecordType::NS, RecordType::A]);
+    let (deleted, added) = transfer::diff(&records, &transfer::resource_records(&zone, &[delegated("meow.catmunch")]));
+    assert_eq!(deleted.len(), 2);
+    assert!(deleted.iter().all(|record| record.name() == &Name::from_str("purr.catmunch.").unwrap()));
+    assert!(added.is_empty());
+}
+#[test]
+fn test_transfer_classless_delegation() {
+    let zone = Zone {
+        name: LowerName::from_str("10.in-addr.arpa.").unwrap(),
+        kind: ZoneKind::Ipv4,
+    };
+    let delegated = |cidr: &str| {
+        Resource::Inetnum(serde_yaml::from_str(&format!("cidr: {}\nns: [{{server: ns.example.com}}]\n", cidr)).unwrap())
+    };
+    // one NS for the child zone and a CNAME per address
+    assert_eq!(transfer::resource_records(&zone, &[delegated("10.1.2.64/26")]).len(), 65);
+    // nothing of a prefix outside of the zone
+    assert!(transfer::resource_records(&zone, &[delegated("172.20.1.64/26")]).is_empty());
+}
+#[test]
+fn test_split_messages() {
+    let records: Vec<Record> = (0..2000)
+        .map(|i| {
+            let text = format!("{:04} {}", i, "x".repeat(60));
+            Record::from_rdata(Name::from_str("purr.catmunch.").unwrap(), 300, RData::TXT(rdata::TXT::new(vec![text])))
+        })
+        .collect();
+    let messages = transfer::split_messages(&records);
+    assert!(messages.len() > 1);
+    for message in &messages {
+        let size: usize = message.iter().map(|record| record.to_bytes().unwrap().len()).sum();
+        assert!(size <= 60000);
+    }
+    assert_eq!(messages.concat(), records);
+    assert_eq!(transfer::split_messages(&records[..1]), [&records[..1]]);
+}
@@ -0,0 +1,164 @@
+use super::{append_ns_records, Zone, ZoneKind};
+use crate::resource::domain::NS;
+use crate::resource::Resource;
+use crate::util::zone::{
+    ipv4_classless_zone, ipv4_reverse_names, ipv4_reverse_zone, ipv6_reverse_names, ipv6_reverse_zone, to_fqdn,
+};
+use cidr::{Ipv4Cidr, Ipv6Cidr};
+use hickory_server::proto::rr::{rdata, LowerName, Name, RData, Record};
+use hickory_server::proto::serialize::binary::BinEncodable;
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+/// Payload budget of one transfer message, below the 65535 bytes limit of DNS over TCP
+const TRANSFER_MESSAGE_SIZE: usize = 60000;
+
+/// All delegations of `zone` in `resources` with their DS and in-zone glue, and the records served inline
+pub fn resource_records<'a>(zone: &Zone, resources: impl IntoIterator<Item = &'a Resource>) -> Vec<Record> {
+    let mut records: Vec<Record> = vec![];
+    let mut glue: Vec<Record> = vec![];
+    for resource in resources {
+        match (resource, &zone.kind) {
+            (Resource::Domain(domain), ZoneKind::Domain) => {
+                let domain_name = Name::from_str(to_fqdn(domain.domain.as_str()).as_str()).unwrap();
+                if domain_name.num_labels() <= zone.name.num_labels() || !zone.name.zone_of(&(&domain_name).into()) {
+                    continue;
+                }
+                if domain.ns.is_empty() {
+                    if let Some(inline_records) = &domain.records {
+                        records.extend(inline_records.to_records(&domain_name, 300).unwrap());
+                    }
+                    continue;
+                }
+                append_ns_records(&domain_name, &domain.ns, &mut records, &mut glue);
+                for rdata in domain.ds.iter().flatten().filter_map(|ds| ds.to_rdata().ok()) {
+                    records.push(Record::from_rdata(domain_name.clone(), 300, rdata));
+                }
+            }
+            (Resource::Inetnum(inetnum), ZoneKind::Ipv4) => {
+                if let Some(ptr) = &inetnum.ptr {
+                    for (address, name) in ptr {
+                        let host = Ipv4Cidr::new(*address, 32).unwrap();
+                        append_ptr_record(zone, &ipv4_reverse_zone(&host), name, &mut records);
+                    }
+                }
+                if let Some(ns_records) = &inetnum.ns {
+                    if inetnum.cidr.network_length() > 24 {
+                        append_classless_delegation(zone, &inetnum.cidr, ns_records, &mut records, &mut glue);
+                        continue;
+                    }
+                    for name in ipv4_reverse_names(&inetnum.cidr) {
+                        append_reverse_delegation(zone, &name, ns_records, &mut records, &mut glue);
+                    }
+                }
+            }
+            (Resource::Inet6num(inet6num), ZoneKind::Ipv6) => {
+                if let Some(ptr) = &inet6num.ptr {
+                    for (address, name) in ptr {
+                        let host = Ipv6Cidr::new(*address, 128).unwrap();
+                        append_ptr_record(zone, &ipv6_reverse_zone(&host), name, &mut records);
+                    }
+                }
+                if let Some(ns_records) = &inet6num.ns {
+                    for name in ipv6_reverse_names(&inet6num.cidr) {
+                        append_reverse_delegation(zone, &name, ns_records, &mut records, &mut glue);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // only glue inside the zone belongs to it, once even when shared by several delegations
+    let mut seen: HashSet<Vec<u8>> = records.iter().map(wire_format).collect();
+    for record in glue {
+        if zone.name.zone_of(&LowerName::from(record.name())) && seen.insert(wire_format(&record)) {
+            records.push(record);
+        }
+    }
+    records
+}
+
+/// Identity of a record for set operations, as `Record` cannot be hashed
+fn wire_format(record: &Record) -> Vec<u8> {
+    record.to_bytes().unwrap_or_default()
+}
+
+fn append_reverse_delegation(
+    zone: &Zone,
+    name: &str,
+    ns_records: &[NS],
+    records: &mut Vec<Record>,
+    glue: &mut Vec<Record>,
+) {
+    let name = Name::from_str(name).unwrap();
+    if name.num_labels() > zone.name.num_labels() && zone.name.zone_of(&(&name).into()) {
+        append_ns_records(&name, ns_records, records, glue);
+    }
+}
+
+/// Reverse name of an address, those from templates are only synthesized when queried
+fn append_ptr_record(zone: &Zone, name: &str, ptr: &str, records: &mut Vec<Record>) {
+    let name = Name::from_str(name).unwrap();
+    if zone.name.zone_of(&(&name).into()) {
+        records.push(Record::from_rdata(
+            name,
+            300,
+            RData::PTR(rdata::PTR(Name::from_str(to_fqdn(ptr).as_str()).unwrap())),
+        ));
+    }
+}
+
+/// RFC 2317 delegation of a prefix longer than /24: the child zone NS and an alias per address
+fn append_classless_delegation(
+    zone: &Zone,
+    cidr: &Ipv4Cidr,
+    ns_records: &[NS],
+    records: &mut Vec<Record>,
+    glue: &mut Vec<Record>,
+) {
+    // the NS and the aliases all are below the /24 of the prefix
+    let first = cidr.first_address().octets();
+    let network = Ipv4Cidr::new(Ipv4Addr::new(first[0], first[1], first[2], 0), 24).unwrap();
+    if !zone.name.zone_of(&LowerName::from(Name::from_str(&ipv4_reverse_zone(&network)).unwrap())) {
+        return;
+    }
+    let classless_zone = ipv4_classless_zone(cidr);
+    append_reverse_delegation(zone, &classless_zone, ns_records, records, glue);
+    for address in cidr.iter().map(|inet| inet.address()) {
+        let host = Ipv4Cidr::new(address, 32).unwrap();
+        let target = format!("{}.{}", address.octets()[3], classless_zone);
+        records.push(Record::from_rdata(
+            Name::from_str(ipv4_reverse_zone(&host).as_str()).unwrap(),
+            300,
+            RData::CNAME(rdata::CNAME(Name::from_str(target.as_str()).unwrap())),
+        ));
+    }
+}
+
+/// Records of `old` missing from `new`, and records of `new` missing from `old`
+pub fn diff(old: &[Record], new: &[Record]) -> (Vec<Record>, Vec<Record>) {
+    let old_set: HashSet<Vec<u8>> = old.iter().map(wire_format).collect();
+    let new_set: HashSet<Vec<u8>> = new.iter().map(wire_format).collect();
+    let deleted = old.iter().filter(|record| !new_set.contains(&wire_format(record))).cloned().collect();
+    let added = new.iter().filter(|record| !old_set.contains(&wire_format(record))).cloned().collect();
+    (deleted, added)
+}
+
+/// Splits the records of a transfer into messages fitting in DNS over TCP
+pub fn split_messages(records: &[Record]) -> Vec<&[Record]> {
+    let mut messages: Vec<&[Record]> = vec![];
+    let mut start = 0;
+    let mut size = 0;
+    for (i, record) in records.iter().enumerate() {
+        let record_size = record.to_bytes().map_or(0, |bytes| bytes.len());
+        if size + record_size > TRANSFER_MESSAGE_SIZE && i > start {
+            messages.push(&records[start..i]);
+            start = i;
+            size = 0;
+        }
+        size += record_size;
+    }
+    messages.push(&records[start..]);
+    messages
+}
@@ -16,31 +16,40 @@ use hickory_client::proto::xfer::{DnsRequestOptions, FirstAnswer};
 use hickory_client::rr::{DNSClass, Name, RecordType};
 use hickory_client::udp::UdpClientStream;
 use crate::config::Config;
+use crate::datasource::{RejectedUpdate, UpdateStatus};
 use crate::store::Store;
+use crate::util::zone::to_fqdn;
 
 struct AppState {
     config: &'static Config,
     store: Box<dyn Store>,
+    status: UpdateStatus,
 }
 
 #[derive(Serialize)]
 struct HealthCheckResult {
     store_ready: bool,
     dns_ready: bool,
-    whois_ready: bool
+    whois_ready: bool,
+    /// Latest revision that could not be loaded, the store still serving the previous one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected_update: Option<RejectedUpdate>,
 }
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 async fn dns_ready(config: &Config) -> Result<()> {
-    if config.dns.len() == 0 {
+    if config.dns.is_empty() {
         return Err(Box::from(SimpleError::new("no dns listening address found")))
     }
+    if config.zones.is_empty() {
+        return Err(Box::from(SimpleError::new("no zone configured")))
+    }
     let addr = config.dns[0];
     let stream = UdpClientStream::<tokio::net::UdpSocket>::new(([127,0,0,1], addr.port()).into());
     let (client, bg) = AsyncClient::connect(stream).await?;
     tokio::spawn(bg);
-    let mut query = Query::query(Name::from_str("ns.catmunch.").unwrap(), RecordType::A);
+    let mut query = Query::query(Name::from_str(to_fqdn(&config.zones[0]).as_str())?, RecordType::SOA);
     query.set_query_class(DNSClass::IN);
     let mut options = DnsRequestOptions::default();
     options.recursion_desired = false;
@@ -49,7 +58,7 @@ async fn dns_ready(config: &Config) -> Result<()> {
 }
 
 async fn whois_ready(config: &Config) -> Result<()> {
-    if config.whois.len() == 0 {
+    if config.whois.is_empty() {
         return Err(Box::from(SimpleError::new("no whois listening address found")))
     }
     let addr = config.whois[0];
@@ -59,7 +68,7 @@ async fn whois_ready(config: &Config) -> Result<()> {
         TcpSocket::new_v6()?
     };
     let mut stream = conn.connect(addr).await?;
-    stream.write("whoami\n".as_bytes()).await?;
+    stream.write_all("whoami\n".as_bytes()).await?;
     let mut result = String::new();
     stream.read_to_string(&mut result).await?;
     if result != config.node_name {
@@ -74,6 +83,7 @@ async fn health_check(data: web::Data<AppState>) -> Result<impl Responder> {
         store_ready: data.store.is_ready(),
         dns_ready: dns_ready(data.config).await.is_ok(),
         whois_ready: whois_ready(data.config).await.is_ok(),
+        rejected_update: data.status.read().unwrap().clone(),
     };
     if result.store_ready && result.whois_ready && result.dns_ready {
         Ok((web::Json(result), http::StatusCode::OK))
@@ -82,12 +92,13 @@ async fn health_check(data: web::Data<AppState>) -> Result<impl Responder> {
     }
 }
 
-pub async fn run_health_check_server(config: &'static Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
+pub async fn run_health_check_server(config: &'static Config, store: Box<dyn Store>, status: UpdateStatus, cancellation_token: CancellationToken) -> io::Result<()> {
     let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(AppState {
                 config,
-                store: store.clone()
+                store: store.clone(),
+                status: status.clone(),
             }))
             .service(health_check)
     })
@@ -100,11 +111,8 @@ pub async fn run_health_check_server(config: &'static Config, store: Box<dyn Sto
             server_handle.stop(true).await;
         }
         res = server => {
-            match res {
-                Err(e) => {
-                    panic!("Health check server stopped with error: {}", e)
-                }
-                Ok(_) => {}
+            if let Err(e) = res {
+                panic!("Health check server stopped with error: {}", e)
             }
         }
     }
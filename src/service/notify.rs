@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use hickory_client::client::{AsyncClient, ClientHandle};
+use hickory_client::op::ResponseCode;
+use hickory_client::rr::{DNSClass, Name, RecordSet, RecordType};
+use hickory_client::udp::UdpClientStream;
+use log::{info, warn};
+use simple_error::SimpleError;
+use tokio::time::sleep;
+use crate::config::Config;
+use crate::util::zone::{ipv4_reverse_zone, ipv6_reverse_zone, to_fqdn};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Time to wait for a secondary to acknowledge a NOTIFY
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Notifies every configured secondary that the served zones changed (RFC 1996)
+pub async fn notify_secondaries(config: &'static Config, serial: u32) {
+    let mut zones: Vec<String> = config.zones.iter().map(|zone| to_fqdn(zone)).collect();
+    zones.extend(config.rdns_ipv4.iter().map(ipv4_reverse_zone));
+    zones.extend(config.rdns_ipv6.iter().map(ipv6_reverse_zone));
+    for target in &config.notify {
+        for zone in &zones {
+            tokio::spawn(notify_with_retry(config, *target, zone.clone(), serial));
+        }
+    }
+}
+
+async fn notify_with_retry(config: &'static Config, target: SocketAddr, zone: String, serial: u32) {
+    let mut delay = Duration::from_secs(config.notify_retry_interval);
+    for attempt in 1..=config.notify_retries {
+        match notify(target, zone.as_str()).await {
+            Ok(()) => {
+                info!("NOTIFY of {} serial {} acknowledged by {}", zone, serial, target);
+                return;
+            }
+            Err(e) => {
+                warn!("NOTIFY of {} serial {} to {} failed (attempt {}/{}): {}", zone, serial, target, attempt, config.notify_retries, e);
+            }
+        }
+        if attempt < config.notify_retries {
+            sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    warn!("NOTIFY of {} serial {} was not acknowledged by {}", zone, serial, target);
+}
+
+async fn notify(target: SocketAddr, zone: &str) -> Result<()> {
+    let stream = UdpClientStream::<tokio::net::UdpSocket>::with_timeout(target, NOTIFY_TIMEOUT);
+    let (mut client, bg) = AsyncClient::connect(stream).await?;
+    tokio::spawn(bg);
+    let response = client
+        .notify(Name::from_str(zone)?, DNSClass::IN, RecordType::SOA, None::<RecordSet>)
+        .await?;
+    if response.response_code() != ResponseCode::NoError {
+        return Err(Box::from(SimpleError::new(format!("answered {}", response.response_code()))));
+    }
+    Ok(())
+}
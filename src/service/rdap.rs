@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+use actix_web::{get, http, web, App, HttpRequest, HttpResponse, HttpServer};
+use cidr::IpCidr;
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use crate::config::Config;
+use crate::resource::contacts::Contacts;
+use crate::resource::domain::Domain;
+use crate::store::Store;
+
+/// Most objects returned by a search, the result set being marked as truncated above
+const RDAP_SEARCH_LIMIT: usize = 100;
+const RDAP_CONTENT_TYPE: &str = "application/rdap+json";
+
+struct AppState {
+    config: &'static Config,
+    store: Box<dyn Store>,
+}
+
+#[derive(Serialize)]
+struct Link {
+    value: String,
+    rel: &'static str,
+    href: String,
+    #[serde(rename = "type")]
+    media_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct Notice {
+    title: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    notice_type: Option<&'static str>,
+    description: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct IpAddresses {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    v4: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    v6: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DsData {
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SecureDns {
+    delegation_signed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ds_data: Vec<DsData>,
+}
+
+/// vCard property as a jCard array (RFC 7095): name, parameters, value type and value
+type VcardProperty = (&'static str, HashMap<&'static str, &'static str>, &'static str, String);
+
+/// Object of any RDAP class (RFC 9083 5), the members irrelevant to its class being left out
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct RdapObject {
+    object_class_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ldh_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_version: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_autnum: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_autnum: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vcard_array: Option<(&'static str, Vec<VcardProperty>)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    roles: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_addresses: Option<IpAddresses>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nameservers: Vec<RdapObject>,
+    #[serde(rename = "secureDNS", skip_serializing_if = "Option::is_none")]
+    secure_dns: Option<SecureDns>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<RdapObject>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remarks: Vec<Notice>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    links: Vec<Link>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RdapResponse<T: Serialize> {
+    rdap_conformance: [&'static str; 1],
+    #[serde(flatten)]
+    body: T,
+    notices: Vec<Notice>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RdapError {
+    error_code: u16,
+    title: String,
+    description: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    name: Option<String>,
+    #[serde(rename = "fn")]
+    full_name: Option<String>,
+    handle: Option<String>,
+}
+
+/// Base URL of the links in responses, as seen by the client
+fn base_url(request: &HttpRequest) -> String {
+    let info = request.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+fn self_link(base: &str, path: String) -> Vec<Link> {
+    let href = format!("{}{}", base, path);
+    vec![Link {
+        value: href.clone(),
+        rel: "self",
+        href,
+        media_type: RDAP_CONTENT_TYPE,
+    }]
+}
+
+fn remarks(description: &Option<String>) -> Vec<Notice> {
+    description
+        .iter()
+        .map(|description| Notice {
+            title: "description".to_string(),
+            notice_type: None,
+            description: description.lines().map(str::to_string).collect(),
+        })
+        .collect()
+}
+
+fn rdap_response<T: Serialize>(status: http::StatusCode, body: T, config: &Config) -> HttpResponse {
+    rdap_response_with_notices(status, body, vec![], config)
+}
+
+/// Response with the conformance of the server and its notices, preceded by `notices`
+fn rdap_response_with_notices<T: Serialize>(
+    status: http::StatusCode,
+    body: T,
+    mut notices: Vec<Notice>,
+    config: &Config,
+) -> HttpResponse {
+    notices.push(Notice {
+        title: "Source".to_string(),
+        notice_type: None,
+        description: vec![format!("Objects of the {} registry.", config.whois_source)],
+    });
+    HttpResponse::build(status)
+        .content_type(RDAP_CONTENT_TYPE)
+        .insert_header((http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+        .json(RdapResponse {
+            rdap_conformance: ["rdap_level_0"],
+            body,
+            notices,
+        })
+}
+
+fn rdap_error(status: http::StatusCode, description: String, config: &Config) -> HttpResponse {
+    let error = RdapError {
+        error_code: status.as_u16(),
+        title: status.canonical_reason().unwrap_or_default().to_string(),
+        description: vec![description],
+    };
+    rdap_response(status, error, config)
+}
+
+/// The person or role with the handle `handle`, or else the mntner named `handle`, as an entity with `roles`
+fn entity(store: &dyn Store, handle: &str, roles: Vec<&'static str>, base: &str) -> RdapObject {
+    let handle = handle.to_uppercase();
+    let text = |name: &'static str, value: &str| (name, HashMap::new(), "text", value.to_string());
+    let mut vcard = vec![text("version", "4.0")];
+    let mut object_remarks = vec![];
+    if let Some(person) = store.get_person(handle.clone()) {
+        vcard.push(text("fn", &person.person));
+        vcard.push(text("kind", "individual"));
+        vcard.extend(person.email.iter().map(|email| text("email", email)));
+        vcard.extend(person.phone.iter().map(|phone| text("tel", phone)));
+    } else if let Some(role) = store.get_role(handle.clone()) {
+        vcard.push(text("fn", &role.role));
+        vcard.push(text("kind", "group"));
+        vcard.extend(role.email.iter().map(|email| text("email", email)));
+    } else if let Some(mntner) = store.get_mntner(handle.clone()) {
+        vcard.push(text("fn", &mntner.mntner));
+        vcard.push(text("kind", "org"));
+        vcard.extend(mntner.email.iter().map(|email| text("email", email)));
+        object_remarks = remarks(&mntner.description);
+    }
+    RdapObject {
+        object_class_name: "entity",
+        links: self_link(base, format!("/entity/{}", handle)),
+        handle: Some(handle),
+        vcard_array: Some(("vcard", vcard)),
+        roles,
+        remarks: object_remarks,
+        ..Default::default()
+    }
+}
+
+/// Entities referenced by an object, with the roles of each: its contacts and its mntners as registrants
+fn entities(contacts: &Contacts, store: &dyn Store, base: &str) -> Vec<RdapObject> {
+    let mut handles: Vec<(String, Vec<&'static str>)> = vec![];
+    for (role, references) in [
+        ("administrative", &contacts.admin_c),
+        ("technical", &contacts.tech_c),
+        ("registrant", &contacts.mnt_by),
+    ] {
+        for reference in references {
+            let reference = reference.to_uppercase();
+            match handles.iter_mut().find(|(handle, _)| *handle == reference) {
+                Some((_, roles)) => roles.push(role),
+                None => handles.push((reference, vec![role])),
+            }
+        }
+    }
+    handles
+        .into_iter()
+        .map(|(handle, roles)| entity(store, &handle, roles, base))
+        .collect()
+}
+
+fn domain_object(domain: &Domain, store: &dyn Store, base: &str) -> RdapObject {
+    let name = domain.domain.trim_end_matches('.').to_lowercase();
+    let nameservers = domain
+        .ns
+        .iter()
+        .map(|ns| RdapObject {
+            object_class_name: "nameserver",
+            ldh_name: Some(ns.server.trim_end_matches('.').to_lowercase()),
+            ip_addresses: (ns.a.is_some() || ns.aaaa.is_some()).then(|| IpAddresses {
+                v4: ns.a.iter().map(|a| a.to_string()).collect(),
+                v6: ns.aaaa.iter().map(|aaaa| aaaa.to_string()).collect(),
+            }),
+            ..Default::default()
+        })
+        .collect();
+    let ds_data: Vec<DsData> = domain
+        .ds
+        .iter()
+        .flatten()
+        .map(|ds| DsData {
+            key_tag: ds.key_tag,
+            algorithm: ds.algorithm,
+            digest_type: ds.digest_type,
+            digest: ds.digest.clone(),
+        })
+        .collect();
+    RdapObject {
+        object_class_name: "domain",
+        handle: Some(name.clone()),
+        links: self_link(base, format!("/domain/{}", name)),
+        ldh_name: Some(name),
+        nameservers,
+        secure_dns: Some(SecureDns {
+            delegation_signed: !ds_data.is_empty(),
+            ds_data,
+        }),
+        entities: entities(&domain.contacts, store, base),
+        remarks: remarks(&domain.description),
+        ..Default::default()
+    }
+}
+
+#[get("/ip/{query:.+}")]
+async fn ip_lookup(request: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> HttpResponse {
+    let store = data.store.as_ref();
+    let base = base_url(&request);
+    let Ok(cidr) = IpCidr::from_str(&path) else {
+        return rdap_error(http::StatusCode::BAD_REQUEST, format!("Invalid IP address or prefix {}", path), data.config);
+    };
+    // the most specific network covering the query
+    let network = match cidr {
+        IpCidr::V4(cidr) => store.get_inetnum_prefixes(cidr).0.pop().map(|inetnum| {
+            (IpCidr::V4(*inetnum.cidr), "v4", inetnum.description, inetnum.contacts)
+        }),
+        IpCidr::V6(cidr) => store.get_inet6num_prefixes(cidr).0.pop().map(|inet6num| {
+            (IpCidr::V6(*inet6num.cidr), "v6", inet6num.description, inet6num.contacts)
+        }),
+    };
+    let Some((cidr, ip_version, description, contacts)) = network else {
+        return rdap_error(http::StatusCode::NOT_FOUND, format!("No network contains {}", path), data.config);
+    };
+    let object = RdapObject {
+        object_class_name: "ip network",
+        handle: Some(cidr.to_string()),
+        start_address: Some(cidr.first_address().to_string()),
+        end_address: Some(cidr.last_address().to_string()),
+        ip_version: Some(ip_version),
+        entities: entities(&contacts, store, &base),
+        remarks: remarks(&description),
+        links: self_link(&base, format!("/ip/{}", cidr)),
+        ..Default::default()
+    };
+    rdap_response(http::StatusCode::OK, object, data.config)
+}
+
+#[get("/autnum/{number}")]
+async fn autnum_lookup(request: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> HttpResponse {
+    let Ok(number) = path.parse::<u32>() else {
+        return rdap_error(http::StatusCode::BAD_REQUEST, format!("Invalid AS number {}", path), data.config);
+    };
+    let Some(autnum) = data.store.get_autnum(format!("AS{}", number)) else {
+        return rdap_error(http::StatusCode::NOT_FOUND, format!("AS{} is not registered", number), data.config);
+    };
+    let base = base_url(&request);
+    let object = RdapObject {
+        object_class_name: "autnum",
+        handle: Some(autnum.autnum),
+        start_autnum: Some(number),
+        end_autnum: Some(number),
+        name: Some(autnum.name),
+        entities: entities(&autnum.contacts, data.store.as_ref(), &base),
+        remarks: remarks(&autnum.description),
+        links: self_link(&base, format!("/autnum/{}", number)),
+        ..Default::default()
+    };
+    rdap_response(http::StatusCode::OK, object, data.config)
+}
+
+#[get("/domain/{name}")]
+async fn domain_lookup(request: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> HttpResponse {
+    let name = path.trim_end_matches('.').to_lowercase();
+    match data.store.get_domain(name.clone()) {
+        Some(domain) => {
+            let object = domain_object(&domain, data.store.as_ref(), &base_url(&request));
+            rdap_response(http::StatusCode::OK, object, data.config)
+        }
+        None => rdap_error(http::StatusCode::NOT_FOUND, format!("{} is not registered", name), data.config),
+    }
+}
+
+#[get("/entity/{handle}")]
+async fn entity_lookup(request: HttpRequest, path: web::Path<String>, data: web::Data<AppState>) -> HttpResponse {
+    let handle = path.to_uppercase();
+    let store = data.store.as_ref();
+    if store.get_person(handle.clone()).is_none()
+        && store.get_role(handle.clone()).is_none()
+        && store.get_mntner(handle.clone()).is_none()
+    {
+        return rdap_error(http::StatusCode::NOT_FOUND, format!("{} is not registered", handle), data.config);
+    }
+    let object = entity(store, &handle, vec![], &base_url(&request));
+    rdap_response(http::StatusCode::OK, object, data.config)
+}
+
+/// Case insensitive matcher of a search pattern, where `*` matches any characters (RFC 9082 4.1)
+fn search_pattern(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern.trim_end_matches('.')).replace(r"\*", ".*");
+    Regex::new(&format!("^(?i){}$", pattern)).unwrap()
+}
+
+/// The first objects matching a search and the notice of the truncation of the others
+fn search_results(objects: Vec<RdapObject>) -> (Vec<RdapObject>, Vec<Notice>) {
+    if objects.len() <= RDAP_SEARCH_LIMIT {
+        return (objects, vec![]);
+    }
+    let truncated = Notice {
+        title: "Search Policy".to_string(),
+        notice_type: Some("result set truncated due to excessive load"),
+        description: vec![format!("Search results are limited to {} objects.", RDAP_SEARCH_LIMIT)],
+    };
+    (objects.into_iter().take(RDAP_SEARCH_LIMIT).collect(), vec![truncated])
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainSearchResults {
+    domain_search_results: Vec<RdapObject>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remarks: Vec<Notice>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntitySearchResults {
+    entity_search_results: Vec<RdapObject>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remarks: Vec<Notice>,
+}
+
+#[get("/domains")]
+async fn domain_search(request: HttpRequest, query: web::Query<SearchQuery>, data: web::Data<AppState>) -> HttpResponse {
+    let Some(name) = &query.name else {
+        return rdap_error(http::StatusCode::BAD_REQUEST, "Domain searches require name".to_string(), data.config);
+    };
+    let pattern = search_pattern(name);
+    let base = base_url(&request);
+    let objects = data
+        .store
+        .search_domains(&|domain| pattern.is_match(domain.trim_end_matches('.')))
+        .iter()
+        .map(|domain| domain_object(domain, data.store.as_ref(), &base))
+        .collect();
+    let (domain_search_results, remarks) = search_results(objects);
+    rdap_response(http::StatusCode::OK, DomainSearchResults { domain_search_results, remarks }, data.config)
+}
+
+#[get("/entities")]
+async fn entity_search(request: HttpRequest, query: web::Query<SearchQuery>, data: web::Data<AppState>) -> HttpResponse {
+    let (pattern, by_name) = match (&query.full_name, &query.handle) {
+        (Some(name), None) => (search_pattern(name), true),
+        (None, Some(handle)) => (search_pattern(handle), false),
+        _ => {
+            let description = "Entity searches require either fn or handle".to_string();
+            return rdap_error(http::StatusCode::BAD_REQUEST, description, data.config);
+        }
+    };
+    let base = base_url(&request);
+    let objects = data
+        .store
+        .search_entities(&|handle, name| pattern.is_match(if by_name { name } else { handle }))
+        .iter()
+        .map(|handle| entity(data.store.as_ref(), handle, vec![], &base))
+        .collect();
+    let (entity_search_results, remarks) = search_results(objects);
+    rdap_response(http::StatusCode::OK, EntitySearchResults { entity_search_results, remarks }, data.config)
+}
+
+#[derive(Serialize)]
+struct Help {}
+
+#[get("/help")]
+async fn help(data: web::Data<AppState>) -> HttpResponse {
+    let notice = Notice {
+        title: "Supported queries".to_string(),
+        notice_type: None,
+        description: vec![
+            "/ip/{address} and /ip/{prefix}/{length}: the most specific inetnum or inet6num".to_string(),
+            "/autnum/{number}".to_string(),
+            "/domain/{name}".to_string(),
+            "/entity/{handle}: persons, roles and mntners".to_string(),
+            "/domains?name={pattern}".to_string(),
+            "/entities?fn={pattern} and /entities?handle={pattern}".to_string(),
+            "Patterns may contain * to match any characters.".to_string(),
+        ],
+    };
+    rdap_response_with_notices(http::StatusCode::OK, Help {}, vec![notice], data.config)
+}
+
+async fn not_found(data: web::Data<AppState>) -> HttpResponse {
+    rdap_error(http::StatusCode::NOT_FOUND, "Unsupported query, see /help".to_string(), data.config)
+}
+
+fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(ip_lookup)
+        .service(autnum_lookup)
+        .service(domain_lookup)
+        .service(entity_lookup)
+        .service(domain_search)
+        .service(entity_search)
+        .service(help)
+        .default_service(web::to(not_found));
+}
+
+pub async fn run_rdap_server(config: &'static Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
+    if config.rdap.is_empty() {
+        return Ok(());
+    }
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(AppState {
+                config,
+                store: store.clone(),
+            }))
+            .configure(configure)
+    })
+    .bind(config.rdap.as_slice())?
+    .run();
+    info!("RDAP server started.");
+    let server_handle = server.handle();
+    select! {
+        _ = cancellation_token.cancelled() => {
+            server_handle.stop(true).await;
+        }
+        res = server => {
+            if let Err(e) = res {
+                panic!("RDAP server stopped with error: {}", e)
+            }
+        }
+    }
+    info!("RDAP server shut down.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,100 @@
+use super::*;
+use actix_web::test;
+use clap::Parser;
+use crate::resource::Resource;
+use crate::store::memory::MemoryStore;
+use serde_json::Value;
+
+fn registry() -> MemoryStore {
+    let mut store = MemoryStore::new();
+    store.set(&[
+        Resource::Autnum(serde_yaml::from_str("autnum: AS64601\nname: MEOW-NET\nadmin_c: [MEOW1-CATMUNCH]\n").unwrap()),
+        Resource::Domain(
+            serde_yaml::from_str(
+                "domain: meow.catmunch\nns: [{server: ns1.meow.catmunch, a: 10.1.0.53}]\n\
+                 ds: [{key_tag: 12345, algorithm: 13, digest_type: 2, digest: abcd}]\nmnt_by: [MEOW-MNT]\n",
+            )
+            .unwrap(),
+        ),
+        Resource::Domain(serde_yaml::from_str("domain: purr.catmunch\n").unwrap()),
+        Resource::Inetnum(serde_yaml::from_str("cidr: 10.1.0.0/16\ndescription: Meow\n").unwrap()),
+        Resource::Inetnum(serde_yaml::from_str("cidr: 10.1.2.0/24\ntech_c: [MEOW1-CATMUNCH]\n").unwrap()),
+        Resource::Inet6num(serde_yaml::from_str("cidr: fc75:1::/32\n").unwrap()),
+        Resource::Person(serde_yaml::from_str("person: Meow Cat\nnic_hdl: MEOW1-CATMUNCH\nemail: [meow@catmunch.net]\n").unwrap()),
+        Resource::Mntner(serde_yaml::from_str("mntner: MEOW-MNT\n").unwrap()),
+    ], 1);
+    store
+}
+
+async fn get(uri: &str) -> (http::StatusCode, Value) {
+    let config: &'static Config = Box::leak(Box::new(Config::parse_from(["dns-whois-server", "--git-repo", "registry"])));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState {
+                config,
+                store: Box::new(registry()),
+            }))
+            .configure(configure),
+    )
+    .await;
+    let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    let status = response.status();
+    assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), RDAP_CONTENT_TYPE);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["rdapConformance"][0], "rdap_level_0");
+    (status, body)
+}
+
+#[actix_web::test]
+async fn test_ip() {
+    let (status, body) = get("/ip/10.1.2.3").await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(body["objectClassName"], "ip network");
+    assert_eq!(body["startAddress"], "10.1.2.0");
+    assert_eq!(body["endAddress"], "10.1.2.255");
+    assert_eq!(body["entities"][0]["roles"][0], "technical");
+    assert_eq!(body["entities"][0]["vcardArray"][1][1], serde_json::json!(["fn", {}, "text", "Meow Cat"]));
+    let (_, body) = get("/ip/10.1.0.0/16").await;
+    assert_eq!(body["handle"], "10.1.0.0/16");
+    assert_eq!(body["remarks"][0]["description"][0], "Meow");
+    assert_eq!(get("/ip/fc75:1:2::/48").await.1["ipVersion"], "v6");
+    assert_eq!(get("/ip/10.9.0.0").await.0, http::StatusCode::NOT_FOUND);
+    assert_eq!(get("/ip/10.1.2.3/16").await.0, http::StatusCode::BAD_REQUEST);
+}
+#[actix_web::test]
+async fn test_autnum_and_domain() {
+    let (status, body) = get("/autnum/64601").await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(body["startAutnum"], 64601);
+    assert_eq!(body["name"], "MEOW-NET");
+    assert_eq!(get("/autnum/64699").await.1["errorCode"], 404);
+    let (_, body) = get("/domain/Meow.catmunch.").await;
+    assert_eq!(body["ldhName"], "meow.catmunch");
+    assert_eq!(body["nameservers"][0]["ipAddresses"]["v4"][0], "10.1.0.53");
+    assert_eq!(body["secureDNS"]["delegationSigned"], true);
+    assert_eq!(body["secureDNS"]["dsData"][0]["keyTag"], 12345);
+    assert_eq!(body["entities"][0]["handle"], "MEOW-MNT");
+    assert_eq!(body["entities"][0]["roles"][0], "registrant");
+    assert!(body["links"][0]["href"].as_str().unwrap().ends_with("/domain/meow.catmunch"));
+}
+#[actix_web::test]
+async fn test_entity_and_search() {
+    let (status, body) = get("/entity/meow1-catmunch").await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(body["handle"], "MEOW1-CATMUNCH");
+    assert_eq!(body["vcardArray"][1][3], serde_json::json!(["email", {}, "text", "meow@catmunch.net"]));
+    assert_eq!(get("/entity/PURR1-CATMUNCH").await.0, http::StatusCode::NOT_FOUND);
+    let (_, body) = get("/domains?name=*.CATMUNCH").await;
+    assert_eq!(body["domainSearchResults"].as_array().unwrap().len(), 2);
+    assert_eq!(body["domainSearchResults"][0]["ldhName"], "meow.catmunch");
+    let (_, body) = get("/domains?name=pu*").await;
+    assert_eq!(body["domainSearchResults"][0]["ldhName"], "purr.catmunch");
+    // mntners are named by their handle
+    let (_, body) = get("/entities?fn=meow*").await;
+    let handles: Vec<&Value> = body["entitySearchResults"].as_array().unwrap().iter().map(|e| &e["handle"]).collect();
+    assert_eq!(handles, ["MEOW-MNT", "MEOW1-CATMUNCH"]);
+    assert_eq!(get("/entities?handle=*-MNT").await.1["entitySearchResults"][0]["handle"], "MEOW-MNT");
+    assert_eq!(get("/entities").await.0, http::StatusCode::BAD_REQUEST);
+    assert_eq!(get("/help").await.1["notices"][0]["title"], "Supported queries");
+    assert_eq!(get("/nameserver/ns1.meow.catmunch").await.0, http::StatusCode::NOT_FOUND);
+}
@@ -1,18 +1,34 @@
 use std::io;
-use crate::config::Config;
+use crate::config::{Config, WhoisFormat};
+use crate::resource::as_set::is_as_set_name;
+use crate::resource::route::Route;
+use crate::resource::route6::Route6;
+use crate::resource::route_set::is_route_set_name;
+use crate::resource::Resource;
 use crate::store::Store;
+use irr::irr_response;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
+use clap::ValueEnum;
 use futures_util::future;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use log::info;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::select;
+use tokio::sync::Semaphore;
+use tokio::time;
 use tokio_util::sync::CancellationToken;
 
+mod irr;
+mod rpsl;
+
 #[derive(Serialize)]
 struct IPResponse<S, T>
 where
@@ -23,94 +39,319 @@ where
     routes: Vec<T>,
 }
 
+#[derive(Serialize)]
+struct OriginResponse {
+    routes: Vec<Route>,
+    route6s: Vec<Route6>,
+}
+
+/// YAML or JSON response, with the referenced contacts when enabled
+#[derive(Serialize)]
+struct WithContacts<'a, T: Serialize> {
+    #[serde(flatten)]
+    value: &'a T,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    contacts: &'a [Resource],
+}
+
 static WHOIS_REQUEST_MAX_LENGTH: u64 = 128;
-async fn handle_whois_request(mut socket: TcpStream, store: Box<dyn Store>, config: Box<Config>) {
+/// Number of tracked source IPs above which those with a full bucket are forgotten, at most once a minute
+const WHOIS_RATE_LIMIT_TABLE_SIZE: usize = 10000;
+/// Longest wait for telling a client turned away that the server is busy
+const WHOIS_BUSY_TIMEOUT: Duration = Duration::from_millis(100);
+fn build_domain_regex(config: &Config) -> Regex {
+    let zones: Vec<String> = config
+        .zones
+        .iter()
+        .map(|zone| regex::escape(zone.trim_end_matches('.').to_lowercase().as_str()))
+        .collect();
+    Regex::new(format!(r"^([a-zA-Z0-9-_]+\.)+({})$", zones.join("|")).as_str()).unwrap()
+}
+
+/// Builds the response to a WHOIS query, optionally preceded by the output format, e.g. `--json AS64601`
+fn whois_response(request: &str, store: &dyn Store, config: &Config, domain_regex: &Regex) -> String {
     lazy_static! {
         static ref ASN_REGEX: Regex = Regex::new(r"^as(\d+)$").unwrap();
-        static ref DOMAIN_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9-_]+\.catmunch$").unwrap();
+        static ref ORIGIN_QUERY_REGEX: Regex = Regex::new(r"^-i\s+origin\s+(as\d+)$").unwrap();
     }
-    let reader = BufReader::new(&mut socket);
-    let mut reader = reader.take(WHOIS_REQUEST_MAX_LENGTH);
-    let mut request = String::new();
-    if reader.read_line(&mut request).await.is_err() {
-        let _ = socket
-            .write("An error occurred when reading the request, please try again.\r\n".as_bytes())
-            .await;
-        return;
+    let mut request = request.trim().to_lowercase();
+    let mut format = config.whois_format;
+    while let Some((flag, query)) = request.split_once(char::is_whitespace) {
+        match flag.strip_prefix("--").and_then(|flag| WhoisFormat::from_str(flag, true).ok()) {
+            Some(flag_format) => format = flag_format,
+            None => break,
+        }
+        request = query.trim_start().to_string();
     }
-    let request = request.trim().to_lowercase();
-    let mut response = format!("No match for {}\r\n", request);
+    let source = config.whois_source.as_str();
+    let mut response = match format {
+        WhoisFormat::Rpsl => rpsl::render(&request, &[], source),
+        _ => format!("No match for {}\r\n", request),
+    };
     if request == "whoami" {
         response = config.node_name.clone();
+    } else if let Some(captures) = ORIGIN_QUERY_REGEX.captures(request.as_str()) {
+        let origin = captures[1].to_uppercase();
+        let (routes, route6s) = store.get_origin_routes(origin.clone());
+        if !routes.is_empty() || !route6s.is_empty() {
+            // only the route objects of the queried origin, out of those of every origin of a route
+            let resources: Vec<Resource> = routes
+                .iter()
+                .map(|route| Resource::Route(Route { origin: vec![origin.clone()], ..route.clone() }))
+                .chain(route6s.iter().map(|route6| Resource::Route6(Route6 { origin: vec![origin.clone()], ..route6.clone() })))
+                .collect();
+            response = render(format, &request, &OriginResponse { routes, route6s }, &resources, store, config);
+        }
     } else if ASN_REGEX.is_match(request.as_str()) {
-        let result = store.get_autnum(request.to_uppercase());
-        if result.is_some() {
-            response = serde_yaml::to_string(&result.unwrap()).unwrap();
+        if let Some(autnum) = store.get_autnum(request.to_uppercase()) {
+            response = render(format, &request, &autnum, &[Resource::Autnum(autnum.clone())], store, config);
+        }
+    } else if is_as_set_name(&request) {
+        if let Some(as_set) = store.get_as_set(request.to_uppercase()) {
+            response = render(format, &request, &as_set, &[Resource::AsSet(as_set.clone())], store, config);
         }
-    } else if DOMAIN_REGEX.is_match(request.as_str()) {
-        let result = store.get_domain(request);
-        if result.is_some() {
-            response = serde_yaml::to_string(&result.unwrap()).unwrap();
+    } else if is_route_set_name(&request) {
+        if let Some(route_set) = store.get_route_set(request.to_uppercase()) {
+            response = render(format, &request, &route_set, &[Resource::RouteSet(route_set.clone())], store, config);
+        }
+    } else if domain_regex.is_match(request.as_str()) {
+        if let Some(domain) = store.get_domain_suffixes(request.clone()).pop() {
+            response = render(format, &request, &domain, &[Resource::Domain(domain.clone())], store, config);
         }
     } else if Ipv4Cidr::from_str(request.as_str()).is_ok() {
         let cidr = Ipv4Cidr::from_str(request.as_str()).unwrap();
         let (inetnums, routes) = store.get_inetnum_prefixes(cidr);
-        let _ = socket
-            .write(
-                serde_yaml::to_string(&IPResponse { inetnums, routes })
-                    .unwrap()
-                    .as_bytes(),
-            )
-            .await;
-        return;
+        let resources: Vec<Resource> = inetnums
+            .iter()
+            .cloned()
+            .map(Resource::Inetnum)
+            .chain(routes.iter().cloned().map(Resource::Route))
+            .collect();
+        response = render(format, &request, &IPResponse { inetnums, routes }, &resources, store, config);
     } else if Ipv6Cidr::from_str(request.as_str()).is_ok() {
         let cidr = Ipv6Cidr::from_str(request.as_str()).unwrap();
         let (inetnums, routes) = store.get_inet6num_prefixes(cidr);
-        let _ = socket
-            .write(
-                serde_yaml::to_string(&IPResponse { inetnums, routes })
-                    .unwrap()
-                    .as_bytes(),
-            )
-            .await;
-        return;
+        let resources: Vec<Resource> = inetnums
+            .iter()
+            .cloned()
+            .map(Resource::Inet6num)
+            .chain(routes.iter().cloned().map(Resource::Route6))
+            .collect();
+        response = render(format, &request, &IPResponse { inetnums, routes }, &resources, store, config);
+    } else if let Some(contact) = contact(store, request.to_uppercase()) {
+        response = render(format, &request, &contact, std::slice::from_ref(&contact), store, config);
     } else {
-        let _ = socket.write("Supported type: autnum (e.g. AS64601), domain (e.g. meow.catmunch), inetnum/route (e.g. 10.0.0.1, 10.0.0.0/16, fc75:adfb:1234::1, fc75:adfb:1234::/48)\r\n".as_bytes()).await;
+        let example_zone = config.zones.first().map_or("catmunch", |zone| zone.trim_end_matches('.'));
+        let supported = format!("Supported type: autnum (e.g. AS64601), domain (e.g. meow.{}), inetnum/route (e.g. 10.0.0.1, 10.0.0.0/16, fc75:adfb:1234::1, fc75:adfb:1234::/48), as-set/route-set (e.g. AS-CATMUNCH, RS-CATMUNCH), person/role/mntner (e.g. MEOW-CATMUNCH), routes of an origin (e.g. -i origin AS64601)", example_zone);
+        response = match format {
+            WhoisFormat::Rpsl => format!("% {}\n{}", supported, response),
+            _ => format!("{}\r\n{}", supported, response),
+        };
     }
-    let _ = socket.write(response.as_bytes()).await;
+    response
 }
 
-pub async fn run_whois_server(config: &Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
-    let mut loops = Vec::new();
-    let config_box = Box::new(config.clone());
-    for addr in &config.whois {
-        let listener = TcpListener::bind(addr).await?;
-        let store = store.clone();
-        let config_box = config_box.clone();
-        let token_copy = cancellation_token.clone();
-        loops.push(tokio::spawn(async move {
-            loop {
-                let store = store.clone();
-                let config_box = config_box.clone();
-                select! {
-                    res = listener.accept() => {
-                        match res {
-                            Ok(_) => {
-                                let (stream, _) = res.unwrap();
-                                tokio::spawn(async move { handle_whois_request(stream, store, config_box).await });
-                            }
-                            Err(_) => {}
-                        }
+/// The person or role with the handle `handle`, or else the mntner named `handle`
+fn contact(store: &dyn Store, handle: String) -> Option<Resource> {
+    store
+        .get_person(handle.clone())
+        .map(Resource::Person)
+        .or_else(|| store.get_role(handle.clone()).map(Resource::Role))
+        .or_else(|| store.get_mntner(handle).map(Resource::Mntner))
+}
+
+/// Renders `value` as YAML or JSON, or else `resources` as RPSL objects, followed by the referenced contacts
+/// when enabled
+fn render<T: Serialize>(
+    format: WhoisFormat,
+    request: &str,
+    value: &T,
+    resources: &[Resource],
+    store: &dyn Store,
+    config: &Config,
+) -> String {
+    let contacts = if config.whois_contacts { referenced_contacts(resources, store) } else { vec![] };
+    let value = WithContacts { value, contacts: &contacts };
+    match format {
+        WhoisFormat::Rpsl => rpsl::render(request, &[resources, &contacts].concat(), &config.whois_source),
+        WhoisFormat::Yaml => serde_yaml::to_string(&value).unwrap(),
+        WhoisFormat::Json => serde_json::to_string_pretty(&value).unwrap() + "\n",
+    }
+}
+
+/// Persons and roles referenced as admin-c or tech-c by `resources`, and mntners referenced as mnt-by, once each
+fn referenced_contacts(resources: &[Resource], store: &dyn Store) -> Vec<Resource> {
+    let mut contacts: Vec<Resource> = vec![];
+    let mut seen: HashSet<String> = HashSet::new();
+    for contact in resources.iter().map(Resource::contacts) {
+        for handle in contact.admin_c.iter().chain(&contact.tech_c) {
+            let handle = handle.to_uppercase();
+            if seen.insert(handle.clone()) {
+                if let Some(person) = store.get_person(handle.clone()) {
+                    contacts.push(Resource::Person(person));
+                } else if let Some(role) = store.get_role(handle) {
+                    contacts.push(Resource::Role(role));
+                }
+            }
+        }
+    }
+    for contact in resources.iter().map(Resource::contacts) {
+        for mntner in &contact.mnt_by {
+            if let Some(mntner) = store.get_mntner(mntner.to_uppercase()).filter(|mntner| seen.insert(mntner.mntner.to_uppercase())) {
+                contacts.push(Resource::Mntner(mntner));
+            }
+        }
+    }
+    contacts
+}
+
+/// Queries per minute allowed to each source IP, as a token bucket holding up to a minute worth of queries
+struct QueryRateLimiter {
+    queries_per_minute: u32,
+    buckets: Mutex<QueryBuckets>,
+}
+
+struct QueryBuckets {
+    /// Tokens and last update per client
+    table: HashMap<IpAddr, (f64, Instant)>,
+    swept: Instant,
+}
+
+impl QueryRateLimiter {
+    fn new(queries_per_minute: u32) -> Self {
+        Self {
+            queries_per_minute,
+            buckets: Mutex::new(QueryBuckets {
+                table: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+    /// Accounts a query of `src`, returning whether it is allowed
+    fn check(&self, src: IpAddr) -> bool {
+        if self.queries_per_minute == 0 {
+            return true;
+        }
+        let capacity = self.queries_per_minute as f64;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        // a bucket refilled for a minute is full again
+        let minute = Duration::from_secs(60);
+        if buckets.table.len() >= WHOIS_RATE_LIMIT_TABLE_SIZE && now.duration_since(buckets.swept) >= minute {
+            buckets.table.retain(|_, (_, updated)| now.duration_since(*updated) < minute);
+            buckets.swept = now;
+        }
+        let (tokens, updated) = buckets.table.entry(src).or_insert((capacity, now));
+        *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * capacity / 60.0).min(capacity);
+        *updated = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/// State shared by the connections of all the WHOIS listeners
+#[derive(Clone)]
+struct WhoisContext {
+    store: Box<dyn Store>,
+    config: Box<Config>,
+    domain_regex: Regex,
+    connections: Arc<Semaphore>,
+    rate_limiter: Arc<QueryRateLimiter>,
+}
+
+/// Serves the queries of a connection, a single one unless the IRRd persistent mode is entered with `!!`,
+/// in which case the queries are answered until `!q`, the end of the connection or an idle timeout
+async fn handle_whois_request(mut socket: TcpStream, context: WhoisContext) {
+    let timeout = Duration::from_secs(context.config.whois_timeout);
+    let peer = socket.peer_addr().ok().map(|peer| peer.ip());
+    let (reader, mut writer) = socket.split();
+    let mut reader = BufReader::new(reader);
+    let mut persistent = false;
+    loop {
+        let mut request = String::new();
+        let read = time::timeout(timeout, (&mut reader).take(WHOIS_REQUEST_MAX_LENGTH).read_line(&mut request)).await;
+        let response = match read {
+            Err(_) | Ok(Ok(0)) if persistent => break,
+            Err(_) => "Timed out waiting for the request.\r\n".to_string(),
+            Ok(Err(_)) => "An error occurred when reading the request, please try again.\r\n".to_string(),
+            Ok(Ok(_)) => match request.trim() {
+                "!!" => {
+                    persistent = true;
+                    continue;
+                }
+                "!q" => break,
+                // accounted per query, as a persistent session can carry any number of them
+                command if peer.is_some_and(|peer| !context.rate_limiter.check(peer)) => {
+                    // IRR clients such as bgpq4 expect an error response to their commands
+                    if command.starts_with('!') {
+                        "F Rate limit exceeded\n".to_string()
+                    } else {
+                        "Rate limit exceeded, please try again later.\r\n".to_string()
                     }
-                    _ = token_copy.cancelled() => {
-                        break
+                }
+                command if command.starts_with('!') => irr_response(command, context.store.as_ref(), &context.config),
+                _ => whois_response(&request, context.store.as_ref(), &context.config, &context.domain_regex),
+            },
+        };
+        let write = time::timeout(timeout, writer.write_all(response.as_bytes())).await;
+        if !persistent || !matches!(write, Ok(Ok(_))) {
+            break;
+        }
+    }
+}
+
+/// Accepts connections on `listener` until cancelled, turning away those above the connection cap
+async fn serve_whois(listener: TcpListener, context: WhoisContext, cancellation_token: CancellationToken) {
+    loop {
+        select! {
+            res = listener.accept() => {
+                if let Ok((mut stream, _)) = res {
+                    let context = context.clone();
+                    match context.connections.clone().try_acquire_owned() {
+                        Ok(permit) => {
+                            tokio::spawn(async move {
+                                handle_whois_request(stream, context).await;
+                                drop(permit);
+                            });
+                        }
+                        // written apart so that a client which does not read cannot stall the accept loop
+                        Err(_) => {
+                            tokio::spawn(async move {
+                                let busy = stream.write_all("Too many connections, please try again later.\r\n".as_bytes());
+                                let _ = time::timeout(WHOIS_BUSY_TIMEOUT, busy).await;
+                            });
+                        }
                     }
                 }
             }
-        }))
+            _ = cancellation_token.cancelled() => {
+                break
+            }
+        }
+    }
+}
+
+pub async fn run_whois_server(config: &Config, store: Box<dyn Store>, cancellation_token: CancellationToken) -> io::Result<()> {
+    let mut loops = Vec::new();
+    let context = WhoisContext {
+        store,
+        config: Box::new(config.clone()),
+        domain_regex: build_domain_regex(config),
+        connections: Arc::new(Semaphore::new(config.whois_max_connections)),
+        rate_limiter: Arc::new(QueryRateLimiter::new(config.whois_queries_per_minute)),
+    };
+    for addr in &config.whois {
+        let listener = TcpListener::bind(addr).await?;
+        loops.push(tokio::spawn(serve_whois(listener, context.clone(), cancellation_token.clone())));
     }
     info!("WHOIS server started.");
     let _ = future::select_all(loops).await;
     info!("WHOIS server shut down.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,72 @@
+use crate::config::Config;
+use crate::resource::as_set::is_as_set_name;
+use crate::resource::route_set::is_route_set_name;
+use crate::store::Store;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Answer carrying data, prefixed with its length (IRRd `A<length>`)
+fn data(data: &str) -> String {
+    format!("A{}\n{}\nC\n", data.len() + 1, data)
+}
+
+/// Answers a command of the IRRd `!` protocol, `!!` and `!q` being handled by the session
+pub fn irr_response(command: &str, store: &dyn Store, config: &Config) -> String {
+    lazy_static! {
+        static ref ORIGIN_REGEX: Regex = Regex::new(r"^(?i)as\d+$").unwrap();
+    }
+    let (name, argument) = if command.is_char_boundary(2) { command.split_at(2) } else { (command, "") };
+    let argument = argument.trim();
+    match name {
+        "!g" | "!6" if ORIGIN_REGEX.is_match(argument) => {
+            let (routes, routes6) = store.get_origin_routes(argument.to_uppercase());
+            let mut prefixes: Vec<String> = if name == "!g" {
+                routes.iter().map(|route| route.cidr.to_string()).collect()
+            } else {
+                routes6.iter().map(|route6| route6.cidr.to_string()).collect()
+            };
+            prefixes.sort();
+            prefixes.dedup();
+            if prefixes.is_empty() {
+                "D\n".to_string()
+            } else {
+                data(&prefixes.join(" "))
+            }
+        }
+        "!g" | "!6" => format!("F Invalid origin {}\n", argument),
+        "!i" => {
+            // members of the set itself, or with `,1` the expanded AS numbers or prefixes
+            let (set, recursive) = match argument.strip_suffix(",1") {
+                Some(set) => (set.to_uppercase(), true),
+                None => (argument.to_uppercase(), false),
+            };
+            let members = if is_as_set_name(&set) {
+                if recursive {
+                    store.expand_as_set(set)
+                } else {
+                    store.get_as_set(set).map(|as_set| as_set.members)
+                }
+            } else if is_route_set_name(&set) {
+                if recursive {
+                    store.expand_route_set(set)
+                } else {
+                    store.get_route_set(set).map(|route_set| route_set.members)
+                }
+            } else {
+                None
+            };
+            match members {
+                Some(members) if members.is_empty() => "C\n".to_string(),
+                Some(members) => data(&members.join(" ")),
+                None => "D\n".to_string(),
+            }
+        }
+        // client identification and source selection, every object being from our only source
+        "!n" => "C\n".to_string(),
+        "!s" if argument == "-lc" => data(&config.whois_source),
+        "!s" if argument.split(',').all(|source| source.eq_ignore_ascii_case(&config.whois_source)) => "C\n".to_string(),
+        "!s" => format!("F Unknown source {}\n", argument),
+        "!v" => data(concat!("dns-whois-server ", env!("CARGO_PKG_VERSION"))),
+        _ => "F Unrecognized command\n".to_string(),
+    }
+}
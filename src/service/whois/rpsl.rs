@@ -0,0 +1,176 @@
+use crate::resource::domain::NS;
+use crate::resource::route_set::member_prefix;
+use crate::resource::Resource;
+
+/// Column at which attribute values start, as in the output of the RIRs
+const RPSL_VALUE_COLUMN: usize = 16;
+
+/// Attributes of an RPSL object, in order
+struct RpslObject {
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl RpslObject {
+    fn new(class: &'static str, key: String) -> Self {
+        Self {
+            attributes: vec![(class, key)],
+        }
+    }
+    fn push(&mut self, name: &'static str, value: impl Into<String>) {
+        self.attributes.push((name, value.into()));
+    }
+    /// Repeats the attribute for each line of a multi-line value such as a description
+    fn push_lines(&mut self, name: &'static str, value: &Option<String>) {
+        for line in value.iter().flat_map(|value| value.lines()) {
+            self.push(name, line.trim_end());
+        }
+    }
+    fn push_nservers(&mut self, ns: &[NS]) {
+        for ns in ns {
+            let mut value = ns.server.trim_end_matches('.').to_string();
+            if let Some(a) = ns.a {
+                value += &format!(" {}", a);
+            }
+            if let Some(aaaa) = ns.aaaa {
+                value += &format!(" {}", aaaa);
+            }
+            self.push("nserver", value);
+        }
+    }
+    fn render(&self) -> String {
+        self.attributes
+            .iter()
+            .map(|(name, value)| format!("{:width$}{}\n", format!("{}:", name), value, width = RPSL_VALUE_COLUMN))
+            .collect()
+    }
+}
+
+/// RPSL objects of a resource, a route with several origins being one object per origin (RFC 2622 8.1)
+fn objects(resource: &Resource, source: &str) -> Vec<RpslObject> {
+    let mut objects = vec![];
+    match resource {
+        Resource::Autnum(autnum) => {
+            let mut object = RpslObject::new("aut-num", autnum.autnum.clone());
+            object.push("as-name", autnum.name.clone());
+            object.push_lines("descr", &autnum.description);
+            objects.push(object);
+        }
+        Resource::Domain(domain) => {
+            let mut object = RpslObject::new("domain", domain.domain.trim_end_matches('.').to_string());
+            object.push_lines("descr", &domain.description);
+            object.push_nservers(&domain.ns);
+            for ds in domain.ds.iter().flatten() {
+                object.push(
+                    "ds-rdata",
+                    format!("{} {} {} {}", ds.key_tag, ds.algorithm, ds.digest_type, ds.digest),
+                );
+            }
+            objects.push(object);
+        }
+        Resource::Inetnum(inetnum) => {
+            let range = format!("{} - {}", inetnum.cidr.first_address(), inetnum.cidr.last_address());
+            let mut object = RpslObject::new("inetnum", range);
+            object.push_lines("descr", &inetnum.description);
+            object.push_nservers(inetnum.ns.as_deref().unwrap_or_default());
+            objects.push(object);
+        }
+        Resource::Inet6num(inet6num) => {
+            let mut object = RpslObject::new("inet6num", inet6num.cidr.to_string());
+            object.push_lines("descr", &inet6num.description);
+            object.push_nservers(inet6num.ns.as_deref().unwrap_or_default());
+            objects.push(object);
+        }
+        Resource::Route(route) => {
+            for origin in &route.origin {
+                let mut object = RpslObject::new("route", route.cidr.to_string());
+                object.push_lines("descr", &route.description);
+                object.push("origin", origin.to_uppercase());
+                objects.push(object);
+            }
+        }
+        Resource::Route6(route6) => {
+            for origin in &route6.origin {
+                let mut object = RpslObject::new("route6", route6.cidr.to_string());
+                object.push_lines("descr", &route6.description);
+                object.push("origin", origin.to_uppercase());
+                objects.push(object);
+            }
+        }
+        Resource::AsSet(as_set) => {
+            let mut object = RpslObject::new("as-set", as_set.as_set.clone());
+            object.push_lines("descr", &as_set.description);
+            for member in &as_set.members {
+                object.push("members", member.clone());
+            }
+            objects.push(object);
+        }
+        Resource::RouteSet(route_set) => {
+            let mut object = RpslObject::new("route-set", route_set.route_set.clone());
+            object.push_lines("descr", &route_set.description);
+            // IPv6 prefixes are only allowed in mp-members (RFC 4012 2.2)
+            for member in &route_set.members {
+                if member_prefix(member).is_some_and(|prefix| prefix.is_ipv6()) {
+                    object.push("mp-members", member.clone());
+                } else {
+                    object.push("members", member.clone());
+                }
+            }
+            objects.push(object);
+        }
+        Resource::Person(person) => {
+            let mut object = RpslObject::new("person", person.person.clone());
+            object.push("nic-hdl", person.nic_hdl.clone());
+            for email in &person.email {
+                object.push("e-mail", email.clone());
+            }
+            for phone in &person.phone {
+                object.push("phone", phone.clone());
+            }
+            objects.push(object);
+        }
+        Resource::Role(role) => {
+            let mut object = RpslObject::new("role", role.role.clone());
+            object.push("nic-hdl", role.nic_hdl.clone());
+            for email in &role.email {
+                object.push("e-mail", email.clone());
+            }
+            objects.push(object);
+        }
+        Resource::Mntner(mntner) => {
+            let mut object = RpslObject::new("mntner", mntner.mntner.clone());
+            object.push_lines("descr", &mntner.description);
+            for email in &mntner.email {
+                object.push("upd-to", email.clone());
+            }
+            objects.push(object);
+        }
+    }
+    let contacts = resource.contacts();
+    for object in &mut objects {
+        for admin_c in &contacts.admin_c {
+            object.push("admin-c", admin_c.clone());
+        }
+        for tech_c in &contacts.tech_c {
+            object.push("tech-c", tech_c.clone());
+        }
+        for mnt_by in &contacts.mnt_by {
+            object.push("mnt-by", mnt_by.clone());
+        }
+        object.push("source", source);
+    }
+    objects
+}
+
+/// Renders the resources matching `query` as RPSL objects separated by blank lines, preceded by `%` comments
+pub fn render(query: &str, resources: &[Resource], source: &str) -> String {
+    let objects: Vec<RpslObject> = resources.iter().flat_map(|resource| objects(resource, source)).collect();
+    if objects.is_empty() {
+        return format!("% No match for '{}'\n\n%ERROR:101: no entries found\n", query);
+    }
+    let mut response = format!("% Information related to '{}'\n", query);
+    for object in objects {
+        response += "\n";
+        response += &object.render();
+    }
+    response
+}
@@ -0,0 +1,300 @@
+use super::*;
+use crate::resource::autnum::Autnum;
+use crate::resource::contacts::Contacts;
+use crate::resource::domain::{Domain, NS, DS};
+use crate::resource::inetnum::Inetnum;
+use crate::resource::route::Route;
+use crate::resource::route6::Route6;
+use crate::store::memory::MemoryStore;
+use crate::util::cidr::{Ipv4CidrWrapper, Ipv6CidrWrapper};
+use clap::Parser;
+use std::net::SocketAddr;
+
+fn registry() -> MemoryStore {
+    let mut store = MemoryStore::new();
+    store.set(&[
+        Resource::Autnum(Autnum {
+            autnum: "AS64601".to_string(),
+            name: "MEOW-NET".to_string(),
+            description: Some("Meow network\nSecond line".to_string()),
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "meow.catmunch".to_string(),
+            description: None,
+            ns: vec![NS {
+                server: "ns1.meow.catmunch".to_string(),
+                a: Some("10.1.0.53".parse().unwrap()),
+                aaaa: None,
+            }],
+            ds: Some(vec![DS {
+                key_tag: 12345,
+                algorithm: 13,
+                digest_type: 2,
+                digest: "abcd".to_string(),
+            }]),
+            records: None,
+            contacts: Contacts {
+                admin_c: vec!["MEOW1-CATMUNCH".to_string()],
+                tech_c: vec!["noc-catmunch".to_string(), "MEOW1-CATMUNCH".to_string()],
+                mnt_by: vec!["MEOW-MNT".to_string()],
+            },
+        }),
+        Resource::Inetnum(Inetnum {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.1.0.0/16").unwrap()),
+            description: Some("Meow".to_string()),
+            ns: None,
+            ptr: None,
+            ptr_template: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Route(Route {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.1.0.0/16").unwrap()),
+            description: None,
+            origin: vec!["AS64601".to_string(), "as64602".to_string()],
+            contacts: Contacts::default(),
+        }),
+        Resource::Route(Route {
+            cidr: Ipv4CidrWrapper(Ipv4Cidr::from_str("10.2.0.0/16").unwrap()),
+            description: None,
+            origin: vec!["AS64602".to_string()],
+            contacts: Contacts::default(),
+        }),
+        Resource::Route6(Route6 {
+            cidr: Ipv6CidrWrapper(Ipv6Cidr::from_str("fc75:1::/32").unwrap()),
+            description: None,
+            origin: vec!["AS64601".to_string()],
+            contacts: Contacts::default(),
+        }),
+        Resource::AsSet(serde_yaml::from_str("as_set: AS-MEOW\nmembers: [AS64601, AS-PURR]\n").unwrap()),
+        Resource::AsSet(serde_yaml::from_str("as_set: AS-PURR\nmembers: [AS64602, AS-MEOW]\n").unwrap()),
+        Resource::RouteSet(serde_yaml::from_str("route_set: RS-MEOW\nmembers: [10.9.0.0/16^+, fc75:9::/32, AS64601]\n").unwrap()),
+        Resource::RouteSet(serde_yaml::from_str("route_set: RS-EMPTY\n").unwrap()),
+        Resource::Person(serde_yaml::from_str("person: Meow Cat\nnic_hdl: MEOW1-CATMUNCH\nemail: [meow@catmunch.net]\nmnt_by: [MEOW-MNT]\n").unwrap()),
+        Resource::Role(serde_yaml::from_str("role: Catmunch NOC\nnic_hdl: NOC-CATMUNCH\nemail: [noc@catmunch.net]\nadmin_c: [MEOW1-CATMUNCH]\n").unwrap()),
+        Resource::Mntner(serde_yaml::from_str("mntner: MEOW-MNT\nadmin_c: [MEOW1-CATMUNCH]\nmnt_by: [MEOW-MNT]\n").unwrap()),
+    ], 1);
+    store
+}
+
+fn respond(args: &[&str], request: &str) -> String {
+    let mut config_args = vec!["dns-whois-server", "--git-repo", "registry", "--zone", "catmunch"];
+    config_args.extend(args);
+    let config = Config::parse_from(config_args);
+    whois_response(request, &registry(), &config, &build_domain_regex(&config))
+}
+
+async fn start(args: &[&str]) -> SocketAddr {
+    let mut config_args = vec!["dns-whois-server", "--git-repo", "registry"];
+    config_args.extend(args);
+    let config = Config::parse_from(config_args);
+    let context = WhoisContext {
+        store: Box::new(registry()),
+        domain_regex: build_domain_regex(&config),
+        connections: Arc::new(Semaphore::new(config.whois_max_connections)),
+        rate_limiter: Arc::new(QueryRateLimiter::new(config.whois_queries_per_minute)),
+        config: Box::new(config),
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_whois(listener, context, CancellationToken::new()));
+    addr
+}
+
+/// Reads until the server closes the connection
+async fn read_to_end(stream: &mut TcpStream) -> String {
+    let mut response = String::new();
+    time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .expect("the server did not close the connection")
+        .unwrap();
+    response
+}
+
+async fn query(addr: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    read_to_end(&mut stream).await
+}
+
+#[test]
+fn test_rpsl_autnum() {
+    assert_eq!(
+        respond(&[], "AS64601\r\n"),
+        "% Information related to 'as64601'\n\n\
+         aut-num:        AS64601\n\
+         as-name:        MEOW-NET\n\
+         descr:          Meow network\n\
+         descr:          Second line\n\
+         source:         CATMUNCH\n"
+    );
+}
+#[test]
+fn test_rpsl_domain() {
+    let response = respond(&["--whois-source", "MEOW"], "www.meow.catmunch\r\n");
+    assert!(response.contains("\ndomain:         meow.catmunch\n"));
+    assert!(response.contains("\nnserver:        ns1.meow.catmunch 10.1.0.53\n"));
+    assert!(response.contains("\nds-rdata:       12345 13 2 abcd\n"));
+    assert!(response.ends_with("\nsource:         MEOW\n"));
+}
+#[test]
+fn test_rpsl_ip() {
+    let response = respond(&[], "10.1.2.3\r\n");
+    let objects: Vec<&str> = response.split("\n\n").skip(1).collect();
+    assert_eq!(objects.len(), 3);
+    assert!(objects[0].starts_with("inetnum:        10.1.0.0 - 10.1.255.255\ndescr:          Meow\n"));
+    // one route object per origin
+    assert!(objects[1].starts_with("route:          10.1.0.0/16\norigin:         AS64601\n"));
+    assert!(objects[2].starts_with("route:          10.1.0.0/16\norigin:         AS64602\n"));
+}
+#[test]
+fn test_rpsl_no_match() {
+    assert!(respond(&[], "AS64699\r\n").contains("%ERROR:101: no entries found\n"));
+    assert!(respond(&[], "10.9.0.0/16\r\n").contains("%ERROR:101: no entries found\n"));
+    assert!(respond(&[], "meow\r\n").starts_with("% Supported type:"));
+}
+#[test]
+fn test_selectable_formats() {
+    assert_eq!(
+        respond(&["--whois-format", "yaml"], "AS64601\r\n"),
+        "autnum: AS64601\nname: MEOW-NET\ndescription: |-\n  Meow network\n  Second line\n"
+    );
+    assert_eq!(respond(&["--whois-format", "yaml"], "AS64699\r\n"), "No match for as64699\r\n");
+    let json: serde_json::Value = serde_json::from_str(&respond(&[], "--json 10.1.2.3\r\n")).unwrap();
+    assert_eq!(json["inetnums"][0]["cidr"], "10.1.0.0/16");
+    assert_eq!(json["routes"][0]["origin"][1], "as64602");
+    assert!(respond(&["--whois-format", "json"], "--rpsl AS64601").starts_with("% Information related to 'as64601'"));
+}
+#[test]
+fn test_rpsl_sets() {
+    assert!(respond(&[], "as-meow\r\n").contains("\nas-set:         AS-MEOW\nmembers:        AS64601\nmembers:        AS-PURR\n"));
+    assert!(respond(&[], "RS-MEOW\r\n").contains("\nmembers:        10.9.0.0/16^+\nmp-members:     fc75:9::/32\nmembers:        AS64601\n"));
+}
+#[test]
+fn test_rpsl_contacts() {
+    let response = respond(&[], "meow.catmunch\r\n");
+    assert!(response.contains("\nadmin-c:        MEOW1-CATMUNCH\ntech-c:         noc-catmunch\ntech-c:         MEOW1-CATMUNCH\nmnt-by:         MEOW-MNT\nsource:         CATMUNCH\n"));
+    assert_eq!(response.split("\n\n").count(), 2);
+    assert!(respond(&[], "meow1-catmunch\r\n").contains("\nperson:         Meow Cat\nnic-hdl:        MEOW1-CATMUNCH\ne-mail:         meow@catmunch.net\nmnt-by:         MEOW-MNT\n"));
+    assert!(respond(&[], "MEOW-MNT\r\n").contains("\nmntner:         MEOW-MNT\n"));
+    // referenced objects follow the answer, each once
+    let objects: Vec<String> = respond(&["--whois-contacts"], "meow.catmunch\r\n")
+        .split("\n\n")
+        .skip(1)
+        .map(|object| object.lines().next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        objects,
+        [
+            "domain:         meow.catmunch",
+            "person:         Meow Cat",
+            "role:           Catmunch NOC",
+            "mntner:         MEOW-MNT"
+        ]
+    );
+    let json: serde_json::Value = serde_json::from_str(&respond(&["--whois-contacts"], "--json meow.catmunch\r\n")).unwrap();
+    assert_eq!(json["domain"], "meow.catmunch");
+    assert_eq!(json["contacts"][1]["nic_hdl"], "NOC-CATMUNCH");
+}
+#[test]
+fn test_origin_query() {
+    let response = respond(&[], "-i origin as64602\r\n");
+    let objects: Vec<&str> = response.split("\n\n").skip(1).collect();
+    assert_eq!(objects.len(), 2);
+    assert!(objects[0].starts_with("route:          10.1.0.0/16\norigin:         AS64602\n"));
+    assert!(objects[1].starts_with("route:          10.2.0.0/16\norigin:         AS64602\n"));
+    assert!(respond(&[], "-i origin AS64699\r\n").contains("%ERROR:101: no entries found\n"));
+}
+#[test]
+fn test_irr_commands() {
+    let store = registry();
+    let config = Config::parse_from(["dns-whois-server", "--git-repo", "registry"]);
+    assert_eq!(irr_response("!gAS64602", &store, &config), "A24\n10.1.0.0/16 10.2.0.0/16\nC\n");
+    assert_eq!(irr_response("!gas64601", &store, &config), "A12\n10.1.0.0/16\nC\n");
+    assert_eq!(irr_response("!6AS64601", &store, &config), "A12\nfc75:1::/32\nC\n");
+    assert_eq!(irr_response("!6AS64602", &store, &config), "D\n");
+    assert_eq!(irr_response("!gmeow", &store, &config), "F Invalid origin meow\n");
+    assert_eq!(irr_response("!iAS-MEOW", &store, &config), "A16\nAS64601 AS-PURR\nC\n");
+    assert_eq!(irr_response("!ias-purr,1", &store, &config), "A16\nAS64601 AS64602\nC\n");
+    assert_eq!(irr_response("!iRS-MEOW,1", &store, &config), "A50\n10.1.0.0/16 10.9.0.0/16^+ fc75:1::/32 fc75:9::/32\nC\n");
+    assert_eq!(irr_response("!iRS-EMPTY,1", &store, &config), "C\n");
+    assert_eq!(irr_response("!iAS-MISSING", &store, &config), "D\n");
+    assert_eq!(irr_response("!nbgpq4", &store, &config), "C\n");
+    assert_eq!(irr_response("!s-lc", &store, &config), "A9\nCATMUNCH\nC\n");
+    assert_eq!(irr_response("!x", &store, &config), "F Unrecognized command\n");
+}
+#[tokio::test]
+async fn test_irr_persistent_session() {
+    let addr = start(&[]).await;
+    assert_eq!(
+        query(addr, "!!\n!nbgpq4\n!gAS64601\n!6AS64601\n!q\n").await,
+        "C\nA12\n10.1.0.0/16\nC\nA12\nfc75:1::/32\nC\n"
+    );
+    // without the persistent mode, the connection is closed after the first answer
+    assert_eq!(query(addr, "!gAS64601\n!6AS64601\n").await, "A12\n10.1.0.0/16\nC\n");
+}
+#[tokio::test]
+async fn test_whoami() {
+    let addr = start(&[]).await;
+    assert_eq!(query(addr, "whoami\r\n").await, "Default Node");
+}
+#[tokio::test]
+async fn test_idle_client_timeout() {
+    let addr = start(&["--whois-timeout", "1"]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let started = Instant::now();
+    assert_eq!(read_to_end(&mut stream).await, "Timed out waiting for the request.\r\n");
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+#[tokio::test]
+async fn test_slowloris_client_timeout() {
+    let addr = start(&["--whois-timeout", "1"]).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let (mut reader, mut writer) = stream.split();
+    // one byte at a time without ever finishing the line
+    let drip = async {
+        for _ in 0..20 {
+            if writer.write_all(b"a").await.is_err() {
+                break;
+            }
+            time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    let mut response = String::new();
+    let read = time::timeout(Duration::from_secs(3), reader.read_to_string(&mut response));
+    let (read, _) = tokio::join!(read, drip);
+    assert!(read.is_ok(), "the server did not close the connection");
+    assert_eq!(response, "Timed out waiting for the request.\r\n");
+}
+#[tokio::test]
+async fn test_connection_cap() {
+    let addr = start(&["--whois-max-connections", "2", "--whois-timeout", "5"]).await;
+    let _idle = [
+        TcpStream::connect(addr).await.unwrap(),
+        TcpStream::connect(addr).await.unwrap(),
+    ];
+    // give the server the time to accept them
+    time::sleep(Duration::from_millis(100)).await;
+    // turned away before sending the query, which would reset the connection as it is never read
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(read_to_end(&mut stream).await, "Too many connections, please try again later.\r\n");
+    drop(_idle);
+    time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(query(addr, "whoami\r\n").await, "Default Node");
+}
+#[tokio::test]
+async fn test_rate_limit() {
+    let addr = start(&["--whois-queries-per-minute", "2"]).await;
+    assert_eq!(query(addr, "whoami\r\n").await, "Default Node");
+    assert_eq!(query(addr, "whoami\r\n").await, "Default Node");
+    assert_eq!(query(addr, "whoami\r\n").await, "Rate limit exceeded, please try again later.\r\n");
+}
+#[tokio::test]
+async fn test_rate_limit_persistent_session() {
+    let addr = start(&["--whois-queries-per-minute", "2"]).await;
+    assert_eq!(
+        query(addr, "!!\n!nbgpq4\n!gAS64601\n!6AS64601\n!6AS64601\n!q\n").await,
+        "C\nA12\n10.1.0.0/16\nC\nF Rate limit exceeded\nF Rate limit exceeded\n"
+    );
+    assert_eq!(query(addr, "whoami\r\n").await, "Rate limit exceeded, please try again later.\r\n");
+}
@@ -1,20 +1,105 @@
+use crate::resource::as_set::AsSet;
 use crate::resource::autnum::Autnum;
 use crate::resource::domain::Domain;
 use crate::resource::inet6num::Inet6num;
 use crate::resource::inetnum::Inetnum;
+use crate::resource::mntner::Mntner;
+use crate::resource::person::Person;
+use crate::resource::role::Role;
 use crate::resource::route::Route;
 use crate::resource::route6::Route6;
+use crate::resource::route_set::RouteSet;
 use crate::resource::Resource;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 pub mod memory;
 
+/// Resources of one `Store::set`, with the serial they were published under
+#[derive(Clone)]
+pub struct Snapshot {
+    pub serial: u32,
+    /// Shared with the other snapshots holding the same resources
+    pub resources: Arc<Vec<Arc<Resource>>>,
+}
+
+/// Change of a served resource, with the registry file it comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(PathBuf, Resource),
+    /// Previous and new resource of the file
+    Modified(PathBuf, Box<Resource>, Resource),
+    Deleted(PathBuf, Resource),
+}
+
+impl Change {
+    /// Resource no longer served after the change
+    pub fn removed(&self) -> Option<&Resource> {
+        match self {
+            Change::Added(..) => None,
+            Change::Modified(_, resource, _) => Some(resource),
+            Change::Deleted(_, resource) => Some(resource),
+        }
+    }
+    /// Resource served after the change
+    pub fn added(&self) -> Option<&Resource> {
+        match self {
+            Change::Added(_, resource) | Change::Modified(_, _, resource) => Some(resource),
+            Change::Deleted(..) => None,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(file, resource) => write!(f, "{}: added {}", file.display(), resource.name()),
+            Change::Modified(file, _, resource) => write!(f, "{}: modified {}", file.display(), resource.name()),
+            Change::Deleted(file, resource) => write!(f, "{}: deleted {}", file.display(), resource.name()),
+        }
+    }
+}
+
 pub trait Store: Send + Sync {
-    fn set(&mut self, resources: &Vec<Resource>);
+    /// Replaces the resources, published under `serial`
+    fn set(&mut self, resources: &[Resource], serial: u32);
+    /// Updates the resources of the latest `set` or `apply` in place, published under `serial`, the removed
+    /// resources being removed first
+    fn apply(&mut self, changes: &[Change], serial: u32);
+    fn get_serial(&self) -> u32;
     fn get_autnum(&self, autnum: String) -> Option<Autnum>;
     fn get_domain(&self, domain: String) -> Option<Domain>;
+    /// Registered domains that are a suffix of `domain`, from the shortest to the longest
+    fn get_domain_suffixes(&self, domain: String) -> Vec<Domain>;
+    /// Whether registered domains exist below `domain`
+    fn has_domain_descendants(&self, domain: String) -> bool;
     fn get_inetnum_prefixes(&self, inetnum: Ipv4Cidr) -> (Vec<Inetnum>, Vec<Route>);
     fn get_inet6num_prefixes(&self, inet6num: Ipv6Cidr) -> (Vec<Inet6num>, Vec<Route6>);
+    /// Routes and route6s originated by `origin`, an upper case AS number such as AS64601
+    fn get_origin_routes(&self, origin: String) -> (Vec<Route>, Vec<Route6>);
+    /// Sets by upper case name
+    fn get_as_set(&self, as_set: String) -> Option<AsSet>;
+    fn get_route_set(&self, route_set: String) -> Option<RouteSet>;
+    /// AS numbers of an as-set and of the sets nested in it, in ascending order
+    fn expand_as_set(&self, as_set: String) -> Option<Vec<String>>;
+    /// Prefixes of a route-set and of the sets nested in it, including the routes originated by its AS members
+    fn expand_route_set(&self, route_set: String) -> Option<Vec<String>>;
+    /// Contacts by upper case handle, and mntners by upper case name
+    fn get_person(&self, nic_hdl: String) -> Option<Person>;
+    fn get_role(&self, nic_hdl: String) -> Option<Role>;
+    fn get_mntner(&self, mntner: String) -> Option<Mntner>;
+    /// Domains whose name is accepted by `matches`, sorted by name
+    fn search_domains(&self, matches: &dyn Fn(&str) -> bool) -> Vec<Domain>;
+    /// Handles of the persons, roles and mntners whose handle and name are accepted by `matches`, sorted
+    fn search_entities(&self, matches: &dyn Fn(&str, &str) -> bool) -> Vec<String>;
+    /// Whether inetnums more specific than `inetnum` exist
+    fn has_inetnum_descendants(&self, inetnum: Ipv4Cidr) -> bool;
+    /// Whether inet6nums more specific than `inet6num` exist
+    fn has_inet6num_descendants(&self, inet6num: Ipv6Cidr) -> bool;
+    /// Snapshots of the latest `set` calls, from the oldest to the current one
+    fn get_snapshots(&self) -> Vec<Snapshot>;
     fn clone_dyn(&self) -> Box<dyn Store>;
     fn is_ready(&self) -> bool;
 }
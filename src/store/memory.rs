@@ -1,81 +1,408 @@
+use crate::resource::as_set::{is_as_set_name, AsSet};
 use crate::resource::autnum::Autnum;
 use crate::resource::domain::Domain;
 use crate::resource::inet6num::Inet6num;
 use crate::resource::inetnum::Inetnum;
+use crate::resource::mntner::Mntner;
+use crate::resource::person::Person;
+use crate::resource::role::Role;
 use crate::resource::route::Route;
 use crate::resource::route6::Route6;
+use crate::resource::route_set::{is_route_set_name, member_prefix, RouteSet};
 use crate::resource::Resource;
-use crate::store::Store;
+use crate::store::{Change, Snapshot, Store};
 use cidr::{Ipv4Cidr, Ipv6Cidr};
+use domaintrie::{to_labels, DomainTrie};
 use iptrie::IPTrie;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, RwLock};
 
+mod domaintrie;
 mod iptrie;
+#[cfg(test)]
+mod tests;
+
+/// Routes and route6s originated by an AS
+type OriginRoutes = (Vec<Route>, Vec<Route6>);
+
+/// Number of snapshots kept for incremental zone transfers
+const SNAPSHOTS_KEPT: usize = 16;
+
+/// Indexes of the resources, replaced or updated together so that readers see a single version of the registry
+struct Indexes {
+    autnums: HashMap<String, Autnum>,
+    /// Domains by lower case name without the trailing dot
+    domains: HashMap<String, Domain>,
+    domain_trie: DomainTrie,
+    trie4: IPTrie,
+    trie6: IPTrie,
+    /// Routes and route6s by upper case origin
+    origins: HashMap<String, OriginRoutes>,
+    as_sets: HashMap<String, AsSet>,
+    route_sets: HashMap<String, RouteSet>,
+    persons: HashMap<String, Person>,
+    roles: HashMap<String, Role>,
+    mntners: HashMap<String, Mntner>,
+}
+
+impl Indexes {
+    fn new() -> Self {
+        Self {
+            autnums: HashMap::new(),
+            domains: HashMap::new(),
+            domain_trie: DomainTrie::new(),
+            trie4: IPTrie::new(),
+            trie6: IPTrie::new(),
+            origins: HashMap::new(),
+            as_sets: HashMap::new(),
+            route_sets: HashMap::new(),
+            persons: HashMap::new(),
+            roles: HashMap::new(),
+            mntners: HashMap::new(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct MemoryStore {
-    autnums: Arc<RwLock<HashMap<String, Autnum>>>,
-    domains: Arc<RwLock<HashMap<String, Domain>>>,
-    trie4: Arc<RwLock<IPTrie>>,
-    trie6: Arc<RwLock<IPTrie>>,
-    ready: bool,
+    indexes: Arc<RwLock<Indexes>>,
+    serial: Arc<AtomicU32>,
+    snapshots: Arc<RwLock<VecDeque<Snapshot>>>,
+    /// Shared by the clones, the first registry being possibly loaded after the services started
+    ready: Arc<AtomicBool>,
 }
 
 impl MemoryStore {
     pub fn new() -> Self {
         Self {
-            autnums: Arc::new(RwLock::new(HashMap::new())),
-            domains: Arc::new(RwLock::new(HashMap::new())),
-            trie4: Arc::new(RwLock::new(IPTrie::new())),
-            trie6: Arc::new(RwLock::new(IPTrie::new())),
-            ready: false
+            indexes: Arc::new(RwLock::new(Indexes::new())),
+            serial: Arc::new(AtomicU32::new(0)),
+            snapshots: Arc::new(RwLock::new(VecDeque::new())),
+            ready: Arc::new(AtomicBool::new(false)),
         }
     }
 }
-impl Store for MemoryStore {
-    fn set(&mut self, resources: &Vec<Resource>) {
-        let mut autnums: HashMap<String, Autnum> = HashMap::new();
-        let mut domains: HashMap<String, Domain> = HashMap::new();
-        let mut trie4 = IPTrie::new();
-        let mut trie6 = IPTrie::new();
-        for resource in resources {
-            match resource {
-                Resource::Autnum(autnum) => {
+/// Key of a domain, names being case insensitive and the trailing dot optional
+fn domain_key(domain: &str) -> String {
+    domain.trim_end_matches('.').to_lowercase()
+}
+
+/// Updates `index`, removing then inserting the resources it holds
+fn reindex<T>(
+    index: &mut T,
+    removed: &[&Resource],
+    added: &[&Resource],
+    remove: impl Fn(&mut T, &Resource),
+    insert: impl Fn(&mut T, &Resource),
+) {
+    for resource in removed {
+        remove(index, resource);
+    }
+    for resource in added {
+        insert(index, resource);
+    }
+}
+
+/// Removes `route` from the routes of its origins, forgetting the origins left without routes
+fn remove_origin_routes<T: PartialEq>(
+    origins: &mut HashMap<String, OriginRoutes>,
+    origin: &[String],
+    routes: fn(&mut OriginRoutes) -> &mut Vec<T>,
+    route: &T,
+) {
+    for origin in origin {
+        if let Some(origin_routes) = origins.get_mut(&origin.to_uppercase()) {
+            routes(origin_routes).retain(|other| other != route);
+            if origin_routes.0.is_empty() && origin_routes.1.is_empty() {
+                origins.remove(&origin.to_uppercase());
+            }
+        }
+    }
+}
+
+impl Indexes {
+    /// Removes then inserts resources in every index
+    fn update(&mut self, removed: &[&Resource], added: &[&Resource]) {
+        reindex(
+            &mut self.autnums,
+            removed,
+            added,
+            |autnums, resource| {
+                if let Resource::Autnum(autnum) = resource {
+                    autnums.remove(&autnum.autnum);
+                }
+            },
+            |autnums, resource| {
+                if let Resource::Autnum(autnum) = resource {
                     autnums.insert(autnum.autnum.clone(), autnum.clone());
                 }
-                Resource::Domain(domain) => {
-                    domains.insert(domain.domain.clone(), domain.clone());
+            },
+        );
+        reindex(
+            &mut self.domains,
+            removed,
+            added,
+            |domains, resource| {
+                if let Resource::Domain(domain) = resource {
+                    domains.remove(&domain_key(&domain.domain));
                 }
-                Resource::Inetnum(_) | Resource::Route(_) => {
-                    trie4.add(&resource);
+            },
+            |domains, resource| {
+                if let Resource::Domain(domain) = resource {
+                    domains.insert(domain_key(&domain.domain), domain.clone());
                 }
-                Resource::Inet6num(_) | Resource::Route6(_) => {
-                    trie6.add(&resource);
+            },
+        );
+        reindex(
+            &mut self.domain_trie,
+            removed,
+            added,
+            |domain_trie, resource| {
+                if let Resource::Domain(domain) = resource {
+                    domain_trie.remove(domain);
                 }
-            }
+            },
+            |domain_trie, resource| {
+                if let Resource::Domain(domain) = resource {
+                    domain_trie.add(domain);
+                }
+            },
+        );
+        reindex(
+            &mut self.trie4,
+            removed,
+            added,
+            |trie4, resource| {
+                if matches!(resource, Resource::Inetnum(_) | Resource::Route(_)) {
+                    trie4.remove(resource);
+                }
+            },
+            |trie4, resource| {
+                if matches!(resource, Resource::Inetnum(_) | Resource::Route(_)) {
+                    trie4.add(resource);
+                }
+            },
+        );
+        reindex(
+            &mut self.trie6,
+            removed,
+            added,
+            |trie6, resource| {
+                if matches!(resource, Resource::Inet6num(_) | Resource::Route6(_)) {
+                    trie6.remove(resource);
+                }
+            },
+            |trie6, resource| {
+                if matches!(resource, Resource::Inet6num(_) | Resource::Route6(_)) {
+                    trie6.add(resource);
+                }
+            },
+        );
+        reindex(
+            &mut self.origins,
+            removed,
+            added,
+            |origins, resource| match resource {
+                Resource::Route(route) => remove_origin_routes(origins, &route.origin, |routes| &mut routes.0, route),
+                Resource::Route6(route6) => {
+                    remove_origin_routes(origins, &route6.origin, |routes| &mut routes.1, route6)
+                }
+                _ => {}
+            },
+            |origins, resource| match resource {
+                Resource::Route(route) => {
+                    for origin in &route.origin {
+                        origins.entry(origin.to_uppercase()).or_default().0.push(route.clone());
+                    }
+                }
+                Resource::Route6(route6) => {
+                    for origin in &route6.origin {
+                        origins.entry(origin.to_uppercase()).or_default().1.push(route6.clone());
+                    }
+                }
+                _ => {}
+            },
+        );
+        reindex(
+            &mut self.as_sets,
+            removed,
+            added,
+            |as_sets, resource| {
+                if let Resource::AsSet(as_set) = resource {
+                    as_sets.remove(&as_set.as_set.to_uppercase());
+                }
+            },
+            |as_sets, resource| {
+                if let Resource::AsSet(as_set) = resource {
+                    as_sets.insert(as_set.as_set.to_uppercase(), as_set.clone());
+                }
+            },
+        );
+        reindex(
+            &mut self.route_sets,
+            removed,
+            added,
+            |route_sets, resource| {
+                if let Resource::RouteSet(route_set) = resource {
+                    route_sets.remove(&route_set.route_set.to_uppercase());
+                }
+            },
+            |route_sets, resource| {
+                if let Resource::RouteSet(route_set) = resource {
+                    route_sets.insert(route_set.route_set.to_uppercase(), route_set.clone());
+                }
+            },
+        );
+        reindex(
+            &mut self.persons,
+            removed,
+            added,
+            |persons, resource| {
+                if let Resource::Person(person) = resource {
+                    persons.remove(&person.nic_hdl.to_uppercase());
+                }
+            },
+            |persons, resource| {
+                if let Resource::Person(person) = resource {
+                    persons.insert(person.nic_hdl.to_uppercase(), person.clone());
+                }
+            },
+        );
+        reindex(
+            &mut self.roles,
+            removed,
+            added,
+            |roles, resource| {
+                if let Resource::Role(role) = resource {
+                    roles.remove(&role.nic_hdl.to_uppercase());
+                }
+            },
+            |roles, resource| {
+                if let Resource::Role(role) = resource {
+                    roles.insert(role.nic_hdl.to_uppercase(), role.clone());
+                }
+            },
+        );
+        reindex(
+            &mut self.mntners,
+            removed,
+            added,
+            |mntners, resource| {
+                if let Resource::Mntner(mntner) = resource {
+                    mntners.remove(&mntner.mntner.to_uppercase());
+                }
+            },
+            |mntners, resource| {
+                if let Resource::Mntner(mntner) = resource {
+                    mntners.insert(mntner.mntner.to_uppercase(), mntner.clone());
+                }
+            },
+        );
+    }
+}
+
+impl MemoryStore {
+    /// Publishes the serial and the resources of a `set` or `apply`
+    fn push_snapshot(&self, resources: Vec<Arc<Resource>>, serial: u32) {
+        let mut snapshots = self.snapshots.write().unwrap();
+        self.serial.store(serial, Ordering::Relaxed);
+        snapshots.push_back(Snapshot {
+            serial,
+            resources: Arc::new(resources),
+        });
+        while snapshots.len() > SNAPSHOTS_KEPT {
+            snapshots.pop_front();
         }
-        *self.autnums.write().unwrap() = autnums;
-        *self.domains.write().unwrap() = domains;
-        *self.trie4.write().unwrap() = trie4;
-        *self.trie6.write().unwrap() = trie6;
-        self.ready = true;
     }
+}
 
-    fn get_autnum(&self, autnum: String) -> Option<Autnum> {
-        let autnums = self.autnums.read().unwrap();
-        match autnums.get(autnum.as_str()) {
-            Some(autnum) => Some(autnum.clone()),
-            None => None,
+/// Adds the AS numbers of `as_set` and of the sets nested in it to `asns`, skipping the sets already `visited`
+fn collect_asns(as_sets: &HashMap<String, AsSet>, as_set: &str, visited: &mut HashSet<String>, asns: &mut BTreeSet<u32>) {
+    let Some(as_set) = as_sets.get(as_set).filter(|_| visited.insert(as_set.to_string())) else {
+        return;
+    };
+    for member in &as_set.members {
+        let member = member.to_uppercase();
+        if let Some(asn) = member.strip_prefix("AS").and_then(|asn| asn.parse().ok()) {
+            asns.insert(asn);
+        } else {
+            collect_asns(as_sets, &member, visited, asns);
         }
     }
+}
 
-    fn get_domain(&self, domain: String) -> Option<Domain> {
-        let domains = self.domains.read().unwrap();
-        match domains.get(domain.as_str()) {
-            Some(domain) => Some(domain.clone()),
-            None => None,
+impl Store for MemoryStore {
+    fn set(&mut self, resources: &[Resource], serial: u32) {
+        // the resources are indexed apart, then published at once by swapping the indexes
+        let mut indexes = Indexes::new();
+        indexes.update(&[], &resources.iter().collect::<Vec<_>>());
+        *self.indexes.write().unwrap() = indexes;
+        self.push_snapshot(resources.iter().cloned().map(Arc::new).collect(), serial);
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    fn apply(&mut self, changes: &[Change], serial: u32) {
+        let removed: Vec<&Resource> = changes.iter().filter_map(Change::removed).collect();
+        let added: Vec<&Resource> = changes.iter().filter_map(Change::added).collect();
+        // the indexes are updated under a single lock, readers seeing either the previous or the next version
+        self.indexes.write().unwrap().update(&removed, &added);
+        // the removed resources are found by name, the resources of a snapshot being shared with the previous ones
+        let mut removed_by_name: HashMap<String, Vec<&Resource>> = HashMap::new();
+        for resource in &removed {
+            removed_by_name.entry(resource.name()).or_default().push(resource);
         }
+        let previous = self.snapshots.read().unwrap().back().map(|snapshot| snapshot.resources.clone());
+        let resources = previous
+            .iter()
+            .flat_map(|resources| resources.iter())
+            .filter(|resource| {
+                removed_by_name
+                    .get(&resource.name())
+                    .is_none_or(|removed| !removed.contains(&resource.as_ref()))
+            })
+            .cloned()
+            .chain(added.into_iter().cloned().map(Arc::new))
+            .collect();
+        self.push_snapshot(resources, serial);
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    fn get_serial(&self) -> u32 {
+        self.serial.load(Ordering::Relaxed)
+    }
+
+    fn get_autnum(&self, autnum: String) -> Option<Autnum> {
+        self.indexes.read().unwrap().autnums.get(autnum.as_str()).cloned()
+    }
+
+    fn get_domain(&self, domain: String) -> Option<Domain> {
+        self.indexes.read().unwrap().domains.get(&domain_key(&domain)).cloned()
+    }
+
+    fn get_domain_suffixes(&self, domain: String) -> Vec<Domain> {
+        let mut domains: Vec<Domain> = Vec::new();
+        self.indexes
+            .read()
+            .unwrap()
+            .domain_trie
+            .traverse(&to_labels(domain.as_str()), |node, _| {
+                if let Some(domain) = &node.domain {
+                    domains.push(domain.clone());
+                }
+            });
+        domains
+    }
+
+    fn has_domain_descendants(&self, domain: String) -> bool {
+        let labels = to_labels(domain.as_str());
+        let mut found = false;
+        self.indexes.read().unwrap().domain_trie.traverse(&labels, |node, depth| {
+            if depth == labels.len() {
+                found = node.has_children();
+            }
+        });
+        found
     }
 
     fn get_inetnum_prefixes(&self, inetnum: Ipv4Cidr) -> (Vec<Inetnum>, Vec<Route>) {
@@ -83,21 +410,16 @@ impl Store for MemoryStore {
         let mut routes: Vec<Route> = Vec::new();
         let address = &inetnum.first_address().octets();
         let target_bit = inetnum.network_length() as usize;
-        self.trie4
+        self.indexes
             .read()
             .unwrap()
+            .trie4
             .traverse(address, 8, target_bit, |node, _| {
-                match &node.inetnum {
-                    Some(Resource::Inetnum(inetnum)) => {
-                        inetnums.push(inetnum.clone());
-                    }
-                    _ => {}
+                if let Some(Resource::Inetnum(inetnum)) = &node.inetnum {
+                    inetnums.push(inetnum.clone());
                 }
-                match &node.route {
-                    Some(Resource::Route(route)) => {
-                        routes.push(route.clone());
-                    }
-                    _ => {}
+                if let Some(Resource::Route(route)) = &node.route {
+                    routes.push(route.clone());
                 }
             });
         (inetnums, routes)
@@ -108,31 +430,161 @@ impl Store for MemoryStore {
         let mut routes: Vec<Route6> = Vec::new();
         let address = &inetnum.first_address().octets();
         let target_bit = inetnum.network_length() as usize;
-        self.trie6
+        self.indexes
             .read()
             .unwrap()
+            .trie6
             .traverse(address, 16, target_bit, |node, _| {
-                match &node.inetnum {
-                    Some(Resource::Inet6num(inetnum)) => {
-                        inetnums.push(inetnum.clone());
-                    }
-                    _ => {}
+                if let Some(Resource::Inet6num(inetnum)) = &node.inetnum {
+                    inetnums.push(inetnum.clone());
                 }
-                match &node.route {
-                    Some(Resource::Route6(route)) => {
-                        routes.push(route.clone());
-                    }
-                    _ => {}
+                if let Some(Resource::Route6(route)) = &node.route {
+                    routes.push(route.clone());
                 }
             });
         (inetnums, routes)
     }
 
+    fn get_origin_routes(&self, origin: String) -> OriginRoutes {
+        let indexes = self.indexes.read().unwrap();
+        let origins = &indexes.origins;
+        origins.get(origin.as_str()).cloned().unwrap_or_default()
+    }
+
+    fn get_as_set(&self, as_set: String) -> Option<AsSet> {
+        self.indexes.read().unwrap().as_sets.get(as_set.as_str()).cloned()
+    }
+
+    fn get_route_set(&self, route_set: String) -> Option<RouteSet> {
+        self.indexes.read().unwrap().route_sets.get(route_set.as_str()).cloned()
+    }
+
+    fn expand_as_set(&self, as_set: String) -> Option<Vec<String>> {
+        let indexes = self.indexes.read().unwrap();
+        let as_sets = &indexes.as_sets;
+        as_sets.get(as_set.as_str())?;
+        let mut asns: BTreeSet<u32> = BTreeSet::new();
+        collect_asns(as_sets, &as_set, &mut HashSet::new(), &mut asns);
+        Some(asns.iter().map(|asn| format!("AS{}", asn)).collect())
+    }
+
+    fn expand_route_set(&self, route_set: String) -> Option<Vec<String>> {
+        let indexes = self.indexes.read().unwrap();
+        let route_sets = &indexes.route_sets;
+        route_sets.get(route_set.as_str())?;
+        let as_sets = &indexes.as_sets;
+        let origins = &indexes.origins;
+        let mut prefixes: Vec<String> = vec![];
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut pending = vec![route_set];
+        // sets may include each other, each one is expanded once
+        while let Some(name) = pending.pop() {
+            let Some(route_set) = route_sets.get(name.as_str()).filter(|_| visited.insert(name.clone())) else {
+                continue;
+            };
+            for member in &route_set.members {
+                if member_prefix(member).is_some() {
+                    prefixes.push(member.clone());
+                    continue;
+                }
+                let member = member.to_uppercase();
+                if is_route_set_name(&member) {
+                    pending.push(member);
+                } else {
+                    let mut asns: BTreeSet<u32> = BTreeSet::new();
+                    if is_as_set_name(&member) {
+                        collect_asns(as_sets, &member, &mut HashSet::new(), &mut asns);
+                    } else if let Some(asn) = member.strip_prefix("AS").and_then(|asn| asn.parse().ok()) {
+                        asns.insert(asn);
+                    }
+                    for (routes, routes6) in asns.iter().filter_map(|asn| origins.get(&format!("AS{}", asn))) {
+                        prefixes.extend(routes.iter().map(|route| route.cidr.to_string()));
+                        prefixes.extend(routes6.iter().map(|route6| route6.cidr.to_string()));
+                    }
+                }
+            }
+        }
+        prefixes.sort();
+        prefixes.dedup();
+        Some(prefixes)
+    }
+
+    fn get_person(&self, nic_hdl: String) -> Option<Person> {
+        self.indexes.read().unwrap().persons.get(nic_hdl.as_str()).cloned()
+    }
+
+    fn get_role(&self, nic_hdl: String) -> Option<Role> {
+        self.indexes.read().unwrap().roles.get(nic_hdl.as_str()).cloned()
+    }
+
+    fn get_mntner(&self, mntner: String) -> Option<Mntner> {
+        self.indexes.read().unwrap().mntners.get(mntner.as_str()).cloned()
+    }
+
+    fn search_domains(&self, matches: &dyn Fn(&str) -> bool) -> Vec<Domain> {
+        let indexes = self.indexes.read().unwrap();
+        let domains = &indexes.domains;
+        let mut found: Vec<Domain> = domains.values().filter(|domain| matches(&domain.domain)).cloned().collect();
+        found.sort_by(|a, b| a.domain.cmp(&b.domain));
+        found
+    }
+
+    fn search_entities(&self, matches: &dyn Fn(&str, &str) -> bool) -> Vec<String> {
+        let indexes = self.indexes.read().unwrap();
+        let persons = &indexes.persons;
+        let roles = &indexes.roles;
+        let mntners = &indexes.mntners;
+        let mut found: Vec<String> = persons
+            .values()
+            .map(|person| (&person.nic_hdl, &person.person))
+            .chain(roles.values().map(|role| (&role.nic_hdl, &role.role)))
+            .chain(mntners.values().map(|mntner| (&mntner.mntner, &mntner.mntner)))
+            .filter(|(handle, name)| matches(handle, name))
+            .map(|(handle, _)| handle.clone())
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn has_inetnum_descendants(&self, inetnum: Ipv4Cidr) -> bool {
+        let mut found = false;
+        let target_bit = inetnum.network_length() as usize;
+        self.indexes
+            .read()
+            .unwrap()
+            .trie4
+            .traverse(&inetnum.first_address().octets(), 8, target_bit, |node, current_bit| {
+                if current_bit == target_bit {
+                    found = node.has_inetnum_below();
+                }
+            });
+        found
+    }
+
+    fn has_inet6num_descendants(&self, inet6num: Ipv6Cidr) -> bool {
+        let mut found = false;
+        let target_bit = inet6num.network_length() as usize;
+        self.indexes
+            .read()
+            .unwrap()
+            .trie6
+            .traverse(&inet6num.first_address().octets(), 16, target_bit, |node, current_bit| {
+                if current_bit == target_bit {
+                    found = node.has_inetnum_below();
+                }
+            });
+        found
+    }
+
+    fn get_snapshots(&self) -> Vec<Snapshot> {
+        self.snapshots.read().unwrap().iter().cloned().collect()
+    }
+
     fn clone_dyn(&self) -> Box<dyn Store> {
         Box::new(self.clone())
     }
 
     fn is_ready(&self) -> bool {
-        self.ready
+        self.ready.load(Ordering::Relaxed)
     }
 }
@@ -0,0 +1,68 @@
+use crate::resource::domain::Domain;
+use std::collections::HashMap;
+
+/// Label-wise trie of registered domains, rooted at the TLD (`meow.catmunch` is stored under `catmunch` -> `meow`)
+pub struct DomainTrie {
+    children: HashMap<String, DomainTrie>,
+    pub domain: Option<Domain>,
+}
+
+/// Splits a domain into lowercase labels ordered from the TLD downward
+pub fn to_labels(domain: &str) -> Vec<String> {
+    domain
+        .trim_end_matches('.')
+        .split('.')
+        .rev()
+        .filter(|label| !label.is_empty())
+        .map(|label| label.to_lowercase())
+        .collect()
+}
+
+impl DomainTrie {
+    pub(crate) fn new() -> Self {
+        Self {
+            children: HashMap::new(),
+            domain: None,
+        }
+    }
+    pub fn traverse<F>(&self, labels: &[String], mut callback: F)
+    where
+        F: FnMut(&DomainTrie, usize),
+    {
+        let mut node = self;
+        callback(node, 0);
+        for (depth, label) in labels.iter().enumerate() {
+            match node.children.get(label) {
+                Some(child) => node = child,
+                None => return,
+            }
+            callback(node, depth + 1);
+        }
+    }
+    pub fn has_children(&self) -> bool {
+        !self.children.is_empty()
+    }
+    pub fn add(&mut self, domain: &Domain) {
+        let mut node = self;
+        for label in to_labels(&domain.domain) {
+            node = node.children.entry(label).or_insert_with(DomainTrie::new);
+        }
+        node.domain = Some(domain.clone());
+    }
+    /// Removes a domain, pruning the nodes left without domains so that `has_children` stays accurate
+    pub fn remove(&mut self, domain: &Domain) {
+        self.remove_labels(&to_labels(&domain.domain));
+    }
+    /// Returns whether this node is left empty
+    fn remove_labels(&mut self, labels: &[String]) -> bool {
+        match labels.split_first() {
+            None => self.domain = None,
+            Some((label, rest)) => {
+                if self.children.get_mut(label).is_some_and(|child| child.remove_labels(rest)) {
+                    self.children.remove(label);
+                }
+            }
+        }
+        self.domain.is_none() && self.children.is_empty()
+    }
+}
@@ -41,6 +41,13 @@ impl IPTrie {
             callback,
         );
     }
+    /// Whether an inetnum is stored below this node
+    pub fn has_inetnum_below(&self) -> bool {
+        self.children
+            .iter()
+            .flatten()
+            .any(|child| child.inetnum.is_some() || child.has_inetnum_below())
+    }
     pub fn traverse_mut<const LEN: usize, F>(
         &mut self,
         address: &[u8; LEN],
@@ -110,6 +117,49 @@ impl IPTrie {
                 panic!("Invalid Resource Type!")
             }
         }
-        return;
+    }
+    /// Removes an inetnum, inet6num, route or route6, leaving the emptied nodes in place
+    pub fn remove(&mut self, resource: &Resource) {
+        match resource {
+            Resource::Inetnum(inetnum) => {
+                let address = &inetnum.cidr.first_address().octets();
+                let target_bit = inetnum.cidr.network_length() as usize;
+                self.traverse_mut(address, 8, target_bit, |node, current_bit| {
+                    if current_bit == target_bit {
+                        node.inetnum = None;
+                    }
+                });
+            }
+            Resource::Inet6num(inet6num) => {
+                let address = &inet6num.cidr.first_address().octets();
+                let target_bit = inet6num.cidr.network_length() as usize;
+                self.traverse_mut(address, 16, target_bit, |node, current_bit| {
+                    if current_bit == target_bit {
+                        node.inetnum = None;
+                    }
+                });
+            }
+            Resource::Route(route) => {
+                let address = &route.cidr.first_address().octets();
+                let target_bit = route.cidr.network_length() as usize;
+                self.traverse_mut(address, 8, target_bit, |node, current_bit| {
+                    if current_bit == target_bit {
+                        node.route = None;
+                    }
+                });
+            }
+            Resource::Route6(route6) => {
+                let address = &route6.cidr.first_address().octets();
+                let target_bit = route6.cidr.network_length() as usize;
+                self.traverse_mut(address, 16, target_bit, |node, current_bit| {
+                    if current_bit == target_bit {
+                        node.route = None;
+                    }
+                });
+            }
+            _ => {
+                panic!("Invalid Resource Type!")
+            }
+        }
     }
 }
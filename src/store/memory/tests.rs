@@ -0,0 +1,132 @@
+use super::*;
+use crate::resource::contacts::Contacts;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[test]
+fn test_mem_store_domain_suffixes() {
+    let mut store = MemoryStore::new();
+    store.set(&Vec::from([
+        Resource::Domain(Domain {
+            domain: "meow.catmunch".to_string(),
+            description: None,
+            ns: Vec::new(),
+            ds: None,
+            records: None,
+            contacts: Contacts::default(),
+        }),
+        Resource::Domain(Domain {
+            domain: "lab.meow.catmunch".to_string(),
+            description: None,
+            ns: Vec::new(),
+            ds: None,
+            records: None,
+            contacts: Contacts::default(),
+        }),
+    ]), 1);
+    let x = store.get_domain_suffixes("host.Lab.meow.catmunch".to_string());
+    assert_eq!(x.len(), 2);
+    assert_eq!(x.last().unwrap().domain, "lab.meow.catmunch");
+    let x = store.get_domain_suffixes("other.meow.catmunch".to_string());
+    assert_eq!(x.len(), 1);
+    assert_eq!(x.first().unwrap().domain, "meow.catmunch");
+    assert!(store.get_domain_suffixes("purr.catmunch".to_string()).is_empty());
+}
+#[test]
+fn test_mem_store_snapshots() {
+    let mut store = MemoryStore::new();
+    for serial in 1..=20 {
+        store.set(&Vec::from([Resource::Domain(Domain {
+            domain: format!("meow{}.catmunch", serial),
+            description: None,
+            ns: Vec::new(),
+            ds: None,
+            records: None,
+            contacts: Contacts::default(),
+        })]), serial);
+    }
+    let snapshots = store.get_snapshots();
+    assert_eq!(snapshots.len(), 16);
+    assert_eq!(snapshots.first().unwrap().serial, 5);
+    assert_eq!(snapshots.last().unwrap().serial, 20);
+    assert_eq!(snapshots.last().unwrap().resources.len(), 1);
+}
+#[test]
+fn test_mem_store_apply() {
+    let mut store = MemoryStore::new();
+    let resource = |yaml: &str| -> Resource {
+        match yaml.split(':').next().unwrap() {
+            "domain" => Resource::Domain(serde_yaml::from_str(yaml).unwrap()),
+            "autnum" => Resource::Autnum(serde_yaml::from_str(yaml).unwrap()),
+            _ => Resource::Route(serde_yaml::from_str(yaml).unwrap()),
+        }
+    };
+    let lab = resource("domain: lab.meow.catmunch\n");
+    let route = resource("cidr: 10.1.0.0/16\norigin: [AS64601]\n");
+    store.set(&[resource("domain: meow.catmunch\n"), lab.clone(), route.clone()], 1);
+    let lab_resource = lab.clone();
+    store.apply(&[
+        Change::Deleted(PathBuf::from("domain/lab.meow.catmunch"), lab),
+        Change::Modified(
+            PathBuf::from("route/10.1.0.0_16"),
+            Box::new(route),
+            resource("cidr: 10.1.0.0/16\norigin: [AS64602]\n"),
+        ),
+        Change::Added(PathBuf::from("autnum/AS64602"), resource("autnum: AS64602\nname: PURR\n")),
+    ], 2);
+    assert!(store.get_domain("lab.meow.catmunch".to_string()).is_none());
+    store.apply(&[Change::Added(PathBuf::from("domain/hiss.catmunch"), resource("domain: Hiss.Catmunch.\n"))], 3);
+    assert!(store.get_domain("hiss.catmunch".to_string()).is_some());
+    assert!(store.get_domain("HISS.catmunch.".to_string()).is_some());
+    assert!(!store.has_domain_descendants("meow.catmunch".to_string()));
+    assert!(store.get_origin_routes("AS64601".to_string()).0.is_empty());
+    assert_eq!(store.get_origin_routes("AS64602".to_string()).0.len(), 1);
+    let (_, routes) = store.get_inetnum_prefixes(Ipv4Cidr::from_str("10.1.2.0/24").unwrap());
+    assert_eq!(routes[0].origin, ["AS64602"]);
+    assert!(store.get_autnum("AS64602".to_string()).is_some());
+    let snapshots = store.get_snapshots();
+    assert_eq!(snapshots[1].resources.len(), 3);
+    // the previous snapshot keeps the removed resources and shares the unchanged ones
+    assert_eq!(*snapshots[0].resources[1], lab_resource);
+    assert!(std::sync::Arc::ptr_eq(&snapshots[0].resources[0], &snapshots[1].resources[0]));
+}
+#[test]
+fn test_mem_store_apply_concurrent_reads() {
+    let mut store = MemoryStore::new();
+    let domain = |description: &str| -> Resource {
+        Resource::Domain(serde_yaml::from_str(&format!("domain: meow.catmunch\ndescription: {}\n", description)).unwrap())
+    };
+    store.set(&[domain("0")], 1);
+    let reader = store.clone();
+    let reads = std::thread::spawn(move || {
+        for _ in 0..10000 {
+            assert!(reader.get_domain("meow.catmunch".to_string()).is_some());
+            assert_eq!(reader.get_domain_suffixes("purr.meow.catmunch".to_string()).len(), 1);
+        }
+    });
+    // a modified domain is never seen missing
+    for serial in 2..1000 {
+        let (previous, next) = (domain(&(serial - 1).to_string()), domain(&serial.to_string()));
+        store.apply(&[Change::Modified(PathBuf::from("domain/meow.catmunch"), Box::new(previous), next)], serial);
+    }
+    reads.join().unwrap();
+}
+#[test]
+fn test_mem_store_set_expansion() {
+    let parse = |yaml: &str| serde_yaml::from_str::<RouteSet>(yaml).unwrap();
+    let mut store = MemoryStore::new();
+    store.set(&Vec::from([
+        // as-sets including each other
+        Resource::AsSet(serde_yaml::from_str("as_set: AS-MEOW\nmembers: [AS64601, as-purr]\n").unwrap()),
+        Resource::AsSet(serde_yaml::from_str("as_set: AS-PURR\nmembers: [AS64602, AS-MEOW, AS-MISSING]\n").unwrap()),
+        Resource::Route(serde_yaml::from_str("cidr: 10.2.0.0/16\norigin: [AS64602]\n").unwrap()),
+        Resource::RouteSet(parse("route_set: RS-MEOW\nmembers: [10.1.0.0/16^+, RS-HISS]\n")),
+        Resource::RouteSet(parse("route_set: RS-HISS\nmembers: [RS-MEOW, fc75:1::/32, AS-PURR]\n")),
+    ]), 1);
+    assert_eq!(store.expand_as_set("AS-PURR".to_string()).unwrap(), ["AS64601", "AS64602"]);
+    assert_eq!(
+        store.expand_route_set("RS-MEOW".to_string()).unwrap(),
+        ["10.1.0.0/16^+", "10.2.0.0/16", "fc75:1::/32"]
+    );
+    assert!(store.expand_as_set("AS-MISSING".to_string()).is_none());
+}
@@ -1 +1,2 @@
 pub mod cidr;
+pub mod zone;
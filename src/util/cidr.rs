@@ -20,7 +20,9 @@ impl Visitor<'_> for Ipv4CidrVisitor {
     where
         E: Error,
     {
-        Ok(Ipv4CidrWrapper(Ipv4Cidr::from_str(v).unwrap()))
+        Ipv4Cidr::from_str(v)
+            .map(Ipv4CidrWrapper)
+            .map_err(|e| E::custom(format!("invalid ipv4 cidr {}: {}", v, e)))
     }
 }
 
@@ -63,14 +65,16 @@ impl Visitor<'_> for Ipv6CidrVisitor {
     type Value = Ipv6CidrWrapper;
 
     fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-        formatter.write_str("an ipv4 cidr")
+        formatter.write_str("an ipv6 cidr")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
     where
         E: Error,
     {
-        Ok(Ipv6CidrWrapper(Ipv6Cidr::from_str(v).unwrap()))
+        Ipv6Cidr::from_str(v)
+            .map(Ipv6CidrWrapper)
+            .map_err(|e| E::custom(format!("invalid ipv6 cidr {}: {}", v, e)))
     }
 }
 
@@ -0,0 +1,157 @@
+use cidr::{Cidr, Ipv4Cidr, Ipv6Cidr};
+use hickory_server::proto::rr::Name;
+use simple_error::SimpleError;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Returns `name` with exactly one trailing dot
+pub fn to_fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.').to_lowercase())
+}
+
+/// Checks that the reverse zone of an IPv4 prefix can be named, its length being a multiple of 8
+pub fn check_ipv4_reverse_prefix(cidr: &Ipv4Cidr) -> Result<(), SimpleError> {
+    if !cidr.network_length().is_multiple_of(8) {
+        return Err(SimpleError::new(format!("{} is not octet-aligned", cidr)));
+    }
+    Ok(())
+}
+
+/// Checks that the reverse zone of an IPv6 prefix can be named, its length being a multiple of 4
+pub fn check_ipv6_reverse_prefix(cidr: &Ipv6Cidr) -> Result<(), SimpleError> {
+    if !cidr.network_length().is_multiple_of(4) {
+        return Err(SimpleError::new(format!("{} is not nibble-aligned", cidr)));
+    }
+    Ok(())
+}
+
+/// Returns the in-addr.arpa zone of an octet-aligned IPv4 prefix, e.g. `10.in-addr.arpa.` for 10.0.0.0/8
+pub fn ipv4_reverse_zone(cidr: &Ipv4Cidr) -> String {
+    if let Err(e) = check_ipv4_reverse_prefix(cidr) {
+        panic!("Invalid reverse prefix: {}", e);
+    }
+    let octets = cidr.first_address().octets();
+    let mut zone = String::new();
+    for octet in octets[..(cidr.network_length() / 8) as usize].iter().rev() {
+        zone.push_str(&format!("{}.", octet));
+    }
+    zone.push_str("in-addr.arpa.");
+    zone
+}
+
+/// Returns the ip6.arpa zone of a nibble-aligned IPv6 prefix, e.g. `5.7.c.f.ip6.arpa.` for fc75::/16
+pub fn ipv6_reverse_zone(cidr: &Ipv6Cidr) -> String {
+    if let Err(e) = check_ipv6_reverse_prefix(cidr) {
+        panic!("Invalid reverse prefix: {}", e);
+    }
+    let digits = u128::from(cidr.first_address());
+    let mut zone = String::new();
+    for i in (0..cidr.network_length() / 4).rev() {
+        zone.push_str(&format!("{:x}.", (digits >> (124 - i * 4)) & 0xf));
+    }
+    zone.push_str("ip6.arpa.");
+    zone
+}
+
+/// Returns the RFC 2317 child zone of a prefix longer than /24, e.g. `64-127.2.1.10.in-addr.arpa.` for 10.1.2.64/26
+pub fn ipv4_classless_zone(cidr: &Ipv4Cidr) -> String {
+    let first = cidr.first_address().octets();
+    let network = Ipv4Cidr::new(Ipv4Addr::new(first[0], first[1], first[2], 0), 24).unwrap();
+    format!("{}-{}.{}", first[3], cidr.last_address().octets()[3], ipv4_reverse_zone(&network))
+}
+
+/// Parses the label of an RFC 2317 child zone within the /24 `network`, e.g. `64-127` within 10.1.2.0/24
+pub fn parse_ipv4_classless_label(label: &str, network: [u8; 3]) -> Option<Ipv4Cidr> {
+    let (first, last) = label.split_once('-')?;
+    let (first, last): (u8, u8) = (first.parse().ok()?, last.parse().ok()?);
+    let size = (last as u32 + 1).checked_sub(first as u32)?;
+    if !size.is_power_of_two() || size > 128 {
+        return None;
+    }
+    Ipv4Cidr::new(Ipv4Addr::new(network[0], network[1], network[2], first), 32 - size.trailing_zeros() as u8).ok()
+}
+
+/// Returns the in-addr.arpa names of a prefix, one per octet-aligned subnet when the prefix is not aligned
+pub fn ipv4_reverse_names(cidr: &Ipv4Cidr) -> Vec<String> {
+    let aligned = cidr.network_length().div_ceil(8) * 8;
+    let first = u32::from(cidr.first_address());
+    (0..1u32 << (aligned - cidr.network_length()))
+        .map(|i| {
+            let address = Ipv4Addr::from(first + i.checked_shl(32 - aligned as u32).unwrap_or(0));
+            ipv4_reverse_zone(&Ipv4Cidr::new(address, aligned).unwrap())
+        })
+        .collect()
+}
+
+/// Returns the ip6.arpa names of a prefix, one per nibble-aligned subnet when the prefix is not aligned
+pub fn ipv6_reverse_names(cidr: &Ipv6Cidr) -> Vec<String> {
+    let aligned = cidr.network_length().div_ceil(4) * 4;
+    let first = u128::from(cidr.first_address());
+    (0..1u128 << (aligned - cidr.network_length()))
+        .map(|i| {
+            let address = Ipv6Addr::from(first + i.checked_shl(128 - aligned as u32).unwrap_or(0));
+            ipv6_reverse_zone(&Ipv6Cidr::new(address, aligned).unwrap())
+        })
+        .collect()
+}
+
+/// Renders a reverse name template, replacing `{ip-dashed}` with the address joined by dashes,
+/// e.g. `10-1-2-3` or `fc75-0001-0000-0000-0000-0000-0000-0001`
+pub fn render_ptr_template(template: &str, address: IpAddr) -> String {
+    let dashed: Vec<String> = match address {
+        IpAddr::V4(address) => address.octets().iter().map(|octet| octet.to_string()).collect(),
+        IpAddr::V6(address) => address.segments().iter().map(|segment| format!("{:04x}", segment)).collect(),
+    };
+    template.replace("{ip-dashed}", &dashed.join("-"))
+}
+
+/// The reverse name of `address`, from the `ptr` map or else the template
+pub fn reverse_name<A: Ord + Copy + Into<IpAddr>>(
+    ptr: Option<&BTreeMap<A, String>>,
+    template: Option<&str>,
+    address: A,
+) -> Option<String> {
+    ptr.and_then(|ptr| ptr.get(&address))
+        .map(|name| to_fqdn(name))
+        .or_else(|| template.map(|template| to_fqdn(&render_ptr_template(template, address.into()))))
+}
+
+/// Checks that the reverse names of `cidr`, an object of `class`, are well-formed and only present when
+/// the prefix is not delegated
+pub fn validate_reverse_names<C>(
+    class: &str,
+    cidr: &C,
+    delegated: bool,
+    ptr: Option<&BTreeMap<C::Address, String>>,
+    template: Option<&str>,
+) -> Result<(), SimpleError>
+where
+    C: Cidr + Display,
+    C::Address: Into<IpAddr> + Display,
+{
+    if delegated && (ptr.is_some() || template.is_some()) {
+        return Err(SimpleError::new(format!("ptr is not allowed on a delegated {}", class)));
+    }
+    for address in ptr.iter().flat_map(|ptr| ptr.keys()) {
+        if !cidr.contains(address) {
+            return Err(SimpleError::new(format!("ptr address {} is outside of {}", address, cidr)));
+        }
+    }
+    if template.is_some_and(|template| !template.contains("{ip-dashed}")) {
+        return Err(SimpleError::new("ptr_template must contain {ip-dashed}"));
+    }
+    for address in [cidr.first_address(), cidr.last_address()] {
+        if let Some(name) = reverse_name(ptr, template, address) {
+            Name::from_str(&name).map_err(|e| SimpleError::new(format!("invalid ptr {}: {}", name, e)))?;
+        }
+    }
+    for name in ptr.iter().flat_map(|ptr| ptr.values()) {
+        Name::from_str(name).map_err(|e| SimpleError::new(format!("invalid ptr {}: {}", name, e)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,10 @@
+use super::*;
+
+#[test]
+fn test_ipv4_classless_zone() {
+    let cidr = Ipv4Cidr::from_str("10.1.2.64/26").unwrap();
+    assert_eq!(ipv4_classless_zone(&cidr), "64-127.2.1.10.in-addr.arpa.");
+    assert_eq!(parse_ipv4_classless_label("64-127", [10, 1, 2]), Some(cidr));
+    assert_eq!(parse_ipv4_classless_label("64-128", [10, 1, 2]), None);
+    assert_eq!(parse_ipv4_classless_label("32-95", [10, 1, 2]), None);
+}
@@ -0,0 +1,236 @@
+use crate::datasource::registry::Files;
+use crate::resource::domain::NS;
+use crate::resource::Resource;
+use hickory_server::proto::rr::Name;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Problem of a registry file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub file: PathBuf,
+    pub message: String,
+    /// Whether the problem is a reference to a missing object, which does not make the object invalid on its own
+    pub reference: bool,
+}
+
+impl ValidationError {
+    pub fn new(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            message: message.into(),
+            reference: false,
+        }
+    }
+    pub fn reference(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            reference: true,
+            ..Self::new(file, message)
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+/// Key under which an object is registered, persons and roles sharing the handles
+fn key(resource: &Resource) -> String {
+    match resource {
+        Resource::Person(person) => format!("nic-hdl {}", person.nic_hdl.to_uppercase()),
+        Resource::Role(role) => format!("nic-hdl {}", role.nic_hdl.to_uppercase()),
+        Resource::Domain(domain) => format!("domain {}", domain.domain.trim_end_matches('.').to_lowercase()),
+        _ => resource.name().to_uppercase(),
+    }
+}
+
+/// Checks every object and the consistency of the registry as a whole, returning all the problems found
+pub fn validate(files: &Files) -> Vec<ValidationError> {
+    let mut errors: Vec<ValidationError> = vec![];
+    check_objects(files, &mut errors);
+    check_duplicates(files, &mut errors);
+    check_delegations(files, &mut errors);
+    check_origins(files, &mut errors);
+    check_glue(files, &mut errors);
+    check_references(files, &mut errors);
+    errors
+}
+
+fn check_objects(files: &Files, errors: &mut Vec<ValidationError>) {
+    for (file, resource) in files {
+        let result = match resource {
+            Resource::Domain(domain) => domain.validate(),
+            Resource::Inetnum(inetnum) => inetnum.validate(),
+            Resource::Inet6num(inet6num) => inet6num.validate(),
+            Resource::AsSet(as_set) => as_set.validate(),
+            Resource::RouteSet(route_set) => route_set.validate(),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            errors.push(ValidationError::new(file, e.to_string()));
+        }
+    }
+}
+
+fn check_duplicates(files: &Files, errors: &mut Vec<ValidationError>) {
+    let mut keys: HashMap<String, &Path> = HashMap::new();
+    for (file, resource) in files {
+        if let Some(first) = keys.insert(key(resource), file) {
+            let message = format!("duplicate {}, also defined in {}", resource.name(), first.display());
+            errors.push(ValidationError::new(file, message));
+        }
+    }
+}
+
+/// Objects below a delegation would never be served, the delegated name servers answering for them
+fn check_delegations(files: &Files, errors: &mut Vec<ValidationError>) {
+    let mut domains: Vec<String> = vec![];
+    let mut inetnums = vec![];
+    let mut inet6nums = vec![];
+    for resource in files.values() {
+        match resource {
+            Resource::Domain(domain) if !domain.ns.is_empty() => {
+                domains.push(domain.domain.trim_end_matches('.').to_lowercase());
+            }
+            Resource::Inetnum(inetnum) if inetnum.ns.is_some() => inetnums.push(*inetnum.cidr),
+            Resource::Inet6num(inet6num) if inet6num.ns.is_some() => inet6nums.push(*inet6num.cidr),
+            _ => {}
+        }
+    }
+    for (file, resource) in files {
+        let delegation = match resource {
+            Resource::Domain(domain) => {
+                let name = domain.domain.trim_end_matches('.').to_lowercase();
+                domains
+                    .iter()
+                    .find(|delegated| name.ends_with(&format!(".{}", delegated)))
+                    .map(|delegated| format!("domain {}", delegated))
+            }
+            Resource::Inetnum(inetnum) => inetnums
+                .iter()
+                .find(|delegated| {
+                    delegated.network_length() < inetnum.cidr.network_length()
+                        && delegated.contains(&inetnum.cidr.first_address())
+                })
+                .map(|delegated| format!("inetnum {}", delegated)),
+            Resource::Inet6num(inet6num) => inet6nums
+                .iter()
+                .find(|delegated| {
+                    delegated.network_length() < inet6num.cidr.network_length()
+                        && delegated.contains(&inet6num.cidr.first_address())
+                })
+                .map(|delegated| format!("inet6num {}", delegated)),
+            _ => None,
+        };
+        if let Some(delegation) = delegation {
+            errors.push(ValidationError::new(file, format!("{} is below the delegated {}", resource.name(), delegation)));
+        }
+    }
+}
+
+fn check_origins(files: &Files, errors: &mut Vec<ValidationError>) {
+    let autnums: HashSet<String> = files
+        .values()
+        .filter_map(|resource| match resource {
+            Resource::Autnum(autnum) => Some(autnum.autnum.to_uppercase()),
+            _ => None,
+        })
+        .collect();
+    for (file, resource) in files {
+        let origins = match resource {
+            Resource::Route(route) => &route.origin,
+            Resource::Route6(route6) => &route6.origin,
+            _ => continue,
+        };
+        if origins.is_empty() {
+            errors.push(ValidationError::new(file, format!("{} has no origin", resource.name())));
+        }
+        for origin in origins.iter().filter(|origin| !autnums.contains(&origin.to_uppercase())) {
+            let message = format!("{} origin {} is not a registered autnum", resource.name(), origin);
+            errors.push(ValidationError::reference(file, message));
+        }
+    }
+}
+
+/// Name servers must have valid names, glue when they are inside the domain they serve, and the same glue
+/// wherever they are used
+fn check_glue(files: &Files, errors: &mut Vec<ValidationError>) {
+    let mut glue: HashMap<String, (Option<Ipv4Addr>, Option<Ipv6Addr>, &Path)> = HashMap::new();
+    for (file, resource) in files {
+        let (ns_records, domain): (&[NS], Option<String>) = match resource {
+            Resource::Domain(domain) => (&domain.ns, Some(domain.domain.trim_end_matches('.').to_lowercase())),
+            Resource::Inetnum(inetnum) => (inetnum.ns.as_deref().unwrap_or_default(), None),
+            Resource::Inet6num(inet6num) => (inet6num.ns.as_deref().unwrap_or_default(), None),
+            _ => continue,
+        };
+        for ns in ns_records {
+            let server = ns.server.trim_end_matches('.').to_lowercase();
+            if let Err(e) = Name::from_str(&server) {
+                errors.push(ValidationError::new(file, format!("invalid ns {}: {}", ns.server, e)));
+                continue;
+            }
+            let in_domain = domain
+                .as_ref()
+                .is_some_and(|domain| server == *domain || server.ends_with(&format!(".{}", domain)));
+            if in_domain && ns.a.is_none() && ns.aaaa.is_none() {
+                let message = format!("ns {} is inside {} and needs a or aaaa glue", ns.server, resource.name());
+                errors.push(ValidationError::new(file, message));
+            }
+            if ns.a.is_none() && ns.aaaa.is_none() {
+                continue;
+            }
+            match glue.get(&server) {
+                Some((a, aaaa, first)) if (*a, *aaaa) != (ns.a, ns.aaaa) => {
+                    let message = format!("ns {} has a different glue in {}", ns.server, first.display());
+                    errors.push(ValidationError::new(file, message));
+                }
+                Some(_) => {}
+                None => {
+                    glue.insert(server, (ns.a, ns.aaaa, file));
+                }
+            }
+        }
+    }
+}
+
+/// Every admin-c and tech-c must be the handle of a person or role, and every mnt-by the name of a mntner,
+/// handles and names being case insensitive
+fn check_references(files: &Files, errors: &mut Vec<ValidationError>) {
+    let mut handles: HashSet<String> = HashSet::new();
+    let mut mntners: HashSet<String> = HashSet::new();
+    for resource in files.values() {
+        match resource {
+            Resource::Person(person) => {
+                handles.insert(person.nic_hdl.to_uppercase());
+            }
+            Resource::Role(role) => {
+                handles.insert(role.nic_hdl.to_uppercase());
+            }
+            Resource::Mntner(mntner) => {
+                mntners.insert(mntner.mntner.to_uppercase());
+            }
+            _ => {}
+        }
+    }
+    for (file, resource) in files {
+        let contacts = resource.contacts();
+        for (attribute, references, known) in [
+            ("admin-c", &contacts.admin_c, &handles),
+            ("tech-c", &contacts.tech_c, &handles),
+            ("mnt-by", &contacts.mnt_by, &mntners),
+        ] {
+            for reference in references.iter().filter(|reference| !known.contains(&reference.to_uppercase())) {
+                let message = format!("{} references unknown {} {}", resource.name(), attribute, reference);
+                errors.push(ValidationError::reference(file, message));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,128 @@
+use super::*;
+use crate::resource::as_set::AsSet;
+use crate::resource::domain::Domain;
+use crate::resource::inet6num::Inet6num;
+use crate::resource::inetnum::Inetnum;
+use crate::resource::route_set::RouteSet;
+use std::net::Ipv4Addr;
+
+#[test]
+fn test_domain_validate() {
+    let parse = |yaml: &str| serde_yaml::from_str::<Domain>(yaml).unwrap().validate();
+    assert!(parse("domain: purr.catmunch\nrecords:\n  a: [10.1.0.80]\n  mx: [{preference: 10, exchange: mx.purr.catmunch.}]\n").is_ok());
+    assert!(parse("domain: purr.catmunch\nrecords:\n  caa: [{tag: issue, value: letsencrypt.org}]\n").is_ok());
+    assert!(parse("domain: purr.catmunch\nns: [{server: ns.purr.catmunch}]\nrecords:\n  a: [10.1.0.80]\n").is_err());
+    assert!(parse("domain: purr.catmunch\nrecords:\n  cname: meow.catmunch.\n  a: [10.1.0.80]\n").is_err());
+    assert!(parse("domain: purr.catmunch\nrecords:\n  srv: [{name: sip.tcp, priority: 0, weight: 0, port: 5060, target: purr.catmunch.}]\n").is_err());
+    assert!(parse("domain: purr.catmunch\nrecords:\n  caa: [{tag: iodef, value: not a url}]\n").is_err());
+    let ds = |digest_type: u8, digest: &str| {
+        format!("domain: meow.catmunch\nns: [{{server: ns.meow.catmunch}}]\nds: [{{key_tag: 1, algorithm: 13, digest_type: {}, digest: {}}}]\n", digest_type, digest)
+    };
+    assert!(parse(&ds(2, "e2d3c916f6deeac73294e8268fb5885044a833fc5459588f4a9184cfc41a5766")).is_ok());
+    assert!(parse(&ds(9, "e2d3c916f6deeac73294e8268fb5885044a833fc5459588f4a9184cfc41a5766")).is_err());
+    assert!(parse(&ds(2, "not hex")).is_err());
+}
+#[test]
+fn test_inetnum_ptr() {
+    use crate::util::zone::render_ptr_template;
+    let parse = |yaml: &str| serde_yaml::from_str::<Inetnum>(yaml).unwrap();
+    let inetnum = parse("cidr: 10.4.0.0/24\nptr: {10.4.0.1: gw.purr.catmunch}\nptr_template: \"{ip-dashed}.pool.purr.catmunch\"\n");
+    assert!(inetnum.validate().is_ok());
+    assert_eq!(inetnum.ptr(Ipv4Addr::new(10, 4, 0, 1)).unwrap(), "gw.purr.catmunch.");
+    assert_eq!(inetnum.ptr(Ipv4Addr::new(10, 4, 0, 2)).unwrap(), "10-4-0-2.pool.purr.catmunch.");
+    assert!(parse("cidr: 10.4.0.0/24\nptr: {10.5.0.1: gw.purr.catmunch}\n").validate().is_err());
+    assert!(parse("cidr: 10.4.0.0/24\nns: []\nptr_template: \"{ip-dashed}.purr.catmunch\"\n").validate().is_err());
+    assert_eq!(
+        render_ptr_template("{ip-dashed}.purr.catmunch", "fc75:2::1".parse().unwrap()),
+        "fc75-0002-0000-0000-0000-0000-0000-0001.purr.catmunch"
+    );
+    let parse6 = |yaml: &str| serde_yaml::from_str::<Inet6num>(yaml).unwrap();
+    let inet6num = parse6("cidr: fc75:2::/32\nptr: {\"fc75:2::1\": gw.purr.catmunch}\n");
+    assert!(inet6num.validate().is_ok());
+    assert_eq!(inet6num.ptr("fc75:2::1".parse().unwrap()).unwrap(), "gw.purr.catmunch.");
+    assert!(inet6num.ptr("fc75:2::2".parse().unwrap()).is_none());
+    assert!(parse6("cidr: fc75:2::/32\nptr: {\"fc75:3::1\": gw.purr.catmunch}\n").validate().is_err());
+    assert!(parse6("cidr: fc75:2::/32\nptr_template: pool.purr.catmunch\n").validate().is_err());
+}
+#[test]
+fn test_set_validate() {
+    let as_set = |yaml: &str| serde_yaml::from_str::<AsSet>(yaml).unwrap().validate();
+    let route_set = |yaml: &str| serde_yaml::from_str::<RouteSet>(yaml).unwrap().validate();
+    assert!(as_set("as_set: AS64601:AS-CUSTOMERS\nmembers: [AS64602, AS-PURR]\n").is_ok());
+    assert!(as_set("as_set: RS-MEOW\n").is_err());
+    assert!(as_set("as_set: AS-MEOW\nmembers: [10.1.0.0/16]\n").is_err());
+    assert!(route_set("route_set: RS-MEOW\nmembers: [10.1.0.0/16^+, fc75:1::/32^48-64, RS-PURR, AS-PURR, AS64601]\n").is_ok());
+    assert!(route_set("route_set: RS-MEOW\nmembers: [10.1.0.1]\n").is_err());
+    assert!(route_set("route_set: RS-MEOW\nmembers: [10.1.0.0/16^x]\n").is_err());
+}
+#[test]
+fn test_validate_references() {
+    let file = |name: &str, yaml: &str, resource: fn(&str) -> Resource| (PathBuf::from(name), resource(yaml));
+    let person = file("person/MEOW1", "person: Meow Cat\nnic_hdl: MEOW1-CATMUNCH\n", |yaml| {
+        Resource::Person(serde_yaml::from_str(yaml).unwrap())
+    });
+    let mntner = file("mntner/MEOW-MNT", "mntner: MEOW-MNT\nadmin_c: [meow1-catmunch]\n", |yaml| {
+        Resource::Mntner(serde_yaml::from_str(yaml).unwrap())
+    });
+    let autnum = |yaml: &str| file("autnum/AS64601", yaml, |yaml| Resource::Autnum(serde_yaml::from_str(yaml).unwrap()));
+    let valid = autnum("autnum: AS64601\nname: MEOW\nadmin_c: [MEOW1-CATMUNCH]\nmnt_by: [MEOW-MNT]\n");
+    assert!(validate(&Files::from([person.clone(), mntner.clone(), valid])).is_empty());
+    let unknown_handle = autnum("autnum: AS64601\nname: MEOW\ntech_c: [PURR1-CATMUNCH]\nmnt_by: [PURR-MNT]\n");
+    // references to missing objects do not make the autnum invalid on its own
+    assert!(validate(&Files::from([person.clone(), mntner.clone(), unknown_handle.clone()]))
+        .iter()
+        .all(|e| e.reference));
+    assert_eq!(
+        validate(&Files::from([person.clone(), mntner.clone(), unknown_handle]))
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "autnum/AS64601: autnum AS64601 references unknown tech-c PURR1-CATMUNCH",
+            "autnum/AS64601: autnum AS64601 references unknown mnt-by PURR-MNT"
+        ]
+    );
+    let role = file("role/MEOW1", "role: Meow NOC\nnic_hdl: meow1-catmunch\n", |yaml| {
+        Resource::Role(serde_yaml::from_str(yaml).unwrap())
+    });
+    let duplicate = &validate(&Files::from([person, role]))[0];
+    assert_eq!(duplicate.to_string(), "role/MEOW1: duplicate role meow1-catmunch, also defined in person/MEOW1");
+    assert!(!duplicate.reference);
+}
+#[test]
+fn test_validate_registry() {
+    let yaml = |name: &str, yaml: &str| {
+        let resource = match name.split('/').next().unwrap() {
+            "autnum" => Resource::Autnum(serde_yaml::from_str(yaml).unwrap()),
+            "domain" => Resource::Domain(serde_yaml::from_str(yaml).unwrap()),
+            "inetnum" => Resource::Inetnum(serde_yaml::from_str(yaml).unwrap()),
+            _ => Resource::Route(serde_yaml::from_str(yaml).unwrap()),
+        };
+        (PathBuf::from(name), resource)
+    };
+    let errors: Vec<String> = validate(&Files::from([
+        yaml("autnum/AS64601", "autnum: AS64601\nname: MEOW\n"),
+        yaml("domain/meow", "domain: meow.catmunch\nns: [{server: ns1.meow.catmunch, a: 10.1.0.53}]\n"),
+        yaml("domain/lab.meow", "domain: lab.meow.catmunch\n"),
+        yaml("domain/purr", "domain: purr.catmunch\nns: [{server: ns1.purr.catmunch}]\n"),
+        yaml("inetnum/10.1", "cidr: 10.1.0.0/16\nns: [{server: ns1.meow.catmunch, a: 10.1.0.54}]\n"),
+        yaml("inetnum/10.1.2", "cidr: 10.1.2.0/24\n"),
+        yaml("route/10.1", "cidr: 10.1.0.0/16\norigin: [AS64601, AS64699]\n"),
+        yaml("route/10.1-bis", "cidr: 10.1.0.0/16\norigin: [as64601]\n"),
+    ]))
+    .iter()
+    .map(|e| e.to_string())
+    .collect();
+    assert_eq!(
+        errors,
+        [
+            "route/10.1-bis: duplicate route 10.1.0.0/16, also defined in route/10.1",
+            "domain/lab.meow: domain lab.meow.catmunch is below the delegated domain meow.catmunch",
+            "inetnum/10.1.2: inetnum 10.1.2.0/24 is below the delegated inetnum 10.1.0.0/16",
+            "route/10.1: route 10.1.0.0/16 origin AS64699 is not a registered autnum",
+            "domain/purr: ns ns1.purr.catmunch is inside domain purr.catmunch and needs a or aaaa glue",
+            "inetnum/10.1: ns ns1.meow.catmunch has a different glue in domain/meow",
+        ]
+    );
+    assert!(serde_yaml::from_str::<Inetnum>("cidr: 10.1.2.3/16\n").is_err());
+}